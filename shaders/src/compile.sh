@@ -20,3 +20,6 @@ shadercross "naming.comp.hlsl" -o "../compiled_shaders/spirv/naming.comp.spv"
 shadercross "snowballs.comp.hlsl" -o "../compiled_shaders/dxil/snowballs.comp.dxil"
 shadercross "snowballs.comp.hlsl" -o "../compiled_shaders/msl/snowballs.comp.msl"
 shadercross "snowballs.comp.hlsl" -o "../compiled_shaders/spirv/snowballs.comp.spv"
+shadercross "snowballs_scored.comp.hlsl" -o "../compiled_shaders/dxil/snowballs_scored.comp.dxil"
+shadercross "snowballs_scored.comp.hlsl" -o "../compiled_shaders/msl/snowballs_scored.comp.msl"
+shadercross "snowballs_scored.comp.hlsl" -o "../compiled_shaders/spirv/snowballs_scored.comp.spv"
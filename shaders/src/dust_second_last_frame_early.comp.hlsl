@@ -7,7 +7,7 @@ StructuredBuffer<uint> initialParticlePositions : register(t1, space0);
 // Input matching particle positions, packed 16 bits X/Y
 StructuredBuffer<uint> matchPositions : register(t2, space0);
 
-// Output buffer (just has a few variables written to by any successful matches)
+// Output buffer (match count, followed by the positions of up to maxCandidates matches, in no particular order)
 RWStructuredBuffer<uint> outBuffer : register(u0, space1);
 
 // Uniforms
@@ -27,6 +27,9 @@ cbuffer uniformBuffer : register(b0, space2)
 
     // How much RNG should be skipped between frames due to the battle text, starting from the second to last frame
     uint initialRngSkipAmount : packoffset(c1);
+
+    // Maximum number of matched positions to write to the output buffer
+    uint maxCandidates : packoffset(c1.y);
 };
 
 // Gets the float value from a 32-bit int RNG value
@@ -137,7 +140,11 @@ void main(uint3 GlobalInvocationID : SV_DispatchThreadID)
         }
     }
 
-    // If we matched, increment the number of matches, and track the position
-    InterlockedAdd(outBuffer[0], 1);
-    outBuffer[1] = startRngPosition - lastFrameRngOffset;
+    // If we matched, increment the number of matches, and track the position (if there's room for it)
+    uint candidateIndex;
+    InterlockedAdd(outBuffer[0], 1, candidateIndex);
+    if (candidateIndex < maxCandidates)
+    {
+        outBuffer[1 + candidateIndex] = startRngPosition - lastFrameRngOffset;
+    }
 }
@@ -1,7 +1,7 @@
 // Input RNG seeds
 StructuredBuffer<uint> rngSeeds : register(t0, space0);
 
-// Output buffer (just has a few variables written to by any successful matches)
+// Output buffer (match count, followed by the seed/position pairs of up to maxCandidates matches, in no particular order)
 RWStructuredBuffer<uint> outBuffer : register(u0, space1);
 
 // Uniforms
@@ -21,6 +21,9 @@ cbuffer uniformBuffer : register(b0, space2)
     uint match2 : packoffset(c0.w);
     uint match3 : packoffset(c1);
     uint match4 : packoffset(c1.y);
+
+    // Maximum number of matched seed/position pairs to write to the output buffer
+    uint maxCandidates : packoffset(c1.z);
 };
 
 [numthreads(64, 1, 1)]
@@ -138,10 +141,14 @@ void main(uint3 GlobalInvocationID : SV_DispatchThreadID)
             (actual3 & match3) == match3 && (~actual3 & match3) == 0 && (guaranteed3 & match3) == guaranteed3 &&
             ((actual4 & 0xff) & match4) == match4 && (~(actual4 & 0xff) & match4) == 0 && ((guaranteed4 & 0xff) & match4) == (guaranteed4 & 0xff))
         {
-            // If we matched, increment the number of matches, and track the seed/position
-            InterlockedAdd(outBuffer[0], 1);
-            outBuffer[1] = rngSeed;
-            outBuffer[2] = i + 2;
+            // If we matched, increment the number of matches, and track the seed/position (if there's room for it)
+            uint candidateIndex;
+            InterlockedAdd(outBuffer[0], 1, candidateIndex);
+            if (candidateIndex < maxCandidates)
+            {
+                outBuffer[1 + (candidateIndex * 2)] = rngSeed;
+                outBuffer[2 + (candidateIndex * 2)] = i + 2;
+            }
             break;
         }
     }
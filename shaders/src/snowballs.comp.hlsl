@@ -4,7 +4,7 @@ StructuredBuffer<uint> snowballData : register(t0, space0);
 // Input matching snowball positions, packed 16 bits X/Y
 StructuredBuffer<uint> matchPositions : register(t1, space0);
 
-// Output buffer (just has a few variables written to by any successful matches)
+// Output buffer (match count, followed by the positions of up to maxCandidates matches, in no particular order)
 RWStructuredBuffer<uint> outBuffer : register(u0, space1);
 
 // Uniforms
@@ -12,6 +12,9 @@ cbuffer uniformBuffer : register(b0, space2)
 {
     // Number of matching snowball positions (size of matchPositions)
     uint matchPositionsCount : packoffset(c0);
+
+    // Maximum number of matched positions to write to the output buffer
+    uint maxCandidates : packoffset(c0.y);
 };
 
 [numthreads(64, 1, 1)]
@@ -62,7 +65,11 @@ void main(uint3 GlobalInvocationID : SV_DispatchThreadID)
         }
     }
 
-    // If we matched, increment the number of matches, and track the position
-    InterlockedAdd(outBuffer[0], 1);
-    outBuffer[1] = startRngPosition;
+    // If we matched, increment the number of matches, and track the position (if there's room for it)
+    uint candidateIndex;
+    InterlockedAdd(outBuffer[0], 1, candidateIndex);
+    if (candidateIndex < maxCandidates)
+    {
+        outBuffer[1 + candidateIndex] = startRngPosition;
+    }
 }
\ No newline at end of file
@@ -0,0 +1,62 @@
+// Input snowball data
+StructuredBuffer<uint> snowballData : register(t0, space0);
+
+// Input matching snowball positions, packed 16 bits X/Y
+StructuredBuffer<uint> matchPositions : register(t1, space0);
+
+// Output buffer (two scores per RNG position: the total squared distance, then the max squared distance and its matching snowball index, packed 24/8 bits)
+RWStructuredBuffer<uint> outBuffer : register(u0, space1);
+
+// Uniforms
+cbuffer uniformBuffer : register(b0, space2)
+{
+    // Number of matching snowball positions (size of matchPositions)
+    uint matchPositionsCount : packoffset(c0);
+};
+
+[numthreads(64, 1, 1)]
+void main(uint3 GlobalInvocationID : SV_DispatchThreadID)
+{
+    // Get snowball data position
+    uint startRngPosition = (uint)(GlobalInvocationID.x);
+    uint snowballDataPosition = startRngPosition << 5;
+
+    // Find the distance from each matching position to its nearest snowball
+    uint totalSquaredDistance = 0;
+    uint maxSquaredDistance = 0;
+    uint maxSquaredDistanceIndex = 0;
+    for (uint i = 0; i < matchPositionsCount; i++)
+    {
+        // 1 matching snowball is packed into a 32-bit int
+        uint mpos = matchPositions[i];
+        int matchX = (int)(mpos >> 16);
+        int matchY = (int)(mpos & 0xffff);
+        uint nearestSquaredDistance = 0xffffffff;
+        for (uint j = 0; j < 32; j++)
+        {
+            // 2 snowballs are packed into a 32-bit int
+            uint snowballPairData = snowballData[snowballDataPosition + j];
+            int snowballX = (int)(snowballPairData & 0xff);
+            int snowballY = (int)((snowballPairData >> 8) & 0xff);
+            int dx = snowballX - matchX;
+            int dy = snowballY - matchY;
+            nearestSquaredDistance = min(nearestSquaredDistance, (uint)((dx * dx) + (dy * dy)));
+            snowballX = (int)((snowballPairData >> 16) & 0xff);
+            snowballY = (int)(snowballPairData >> 24);
+            dx = snowballX - matchX;
+            dy = snowballY - matchY;
+            nearestSquaredDistance = min(nearestSquaredDistance, (uint)((dx * dx) + (dy * dy)));
+        }
+
+        totalSquaredDistance += nearestSquaredDistance;
+        if (nearestSquaredDistance > maxSquaredDistance)
+        {
+            maxSquaredDistance = nearestSquaredDistance;
+            maxSquaredDistanceIndex = i;
+        }
+    }
+
+    // Write scores for this position
+    outBuffer[startRngPosition * 2] = totalSquaredDistance;
+    outBuffer[(startRngPosition * 2) + 1] = (maxSquaredDistance << 8) | maxSquaredDistanceIndex;
+}
@@ -0,0 +1,375 @@
+use std::{collections::HashMap, path::Path, str::FromStr, sync::{atomic::AtomicBool, Arc, Mutex}, thread, time::Duration};
+
+use serde_json::{json, Value};
+
+use crate::{compute_backend::{self, SearchJob}, compute_dust_search::{self, DustSearchMode, DustSearchParameters, DustSearchResult},
+            compute_naming_search::{self, NamingSearchParameters, NamingSearchResult}, compute_shaders::PointU32,
+            compute_snowball_search::{self, SnowballSearchMode, SnowballSearchParameters, SnowballSearchResult},
+            config::{Config, ConfigComputeBackend}, encounter_data::Battlegroup, mock_client::{self, MockClient}, rng::{LinearRNG, RNG}, rng_cache};
+
+const USAGE: &str = "Usage:
+  dust-manipulator naming-search --pixels <104 digits of 0/1>
+  dust-manipulator snowball-search --seed <seed> --pos <position> --points <x,y;x,y;...> [--mode exact|scored] [--range <count>]
+  dust-manipulator dust-search --seed <seed> --pos <position> --battlegroup <name> --points <x,y;x,y;...> [--attacks <count>] [--early] [--level-up]
+  dust-manipulator rng dump --seed <seed> --from <position> --count <count>
+  dust-manipulator mock-client --script <file> [--port <port>] [--linger <milliseconds>]
+
+Every subcommand also accepts --profile <name>, and searches also accept --backend auto|gpu|cpu and --max-candidates <count>.
+The mock client stands in for the OBS plugin, connecting to a running tool and following a script with one step per line:
+  screenshots <file> [<file> ...], hotkey <1-5>, wait <milliseconds> or goodbye
+
+Results are printed as JSON, and anything else (such as progress from searches) goes to stderr.
+Without a subcommand, the tool launches as usual.";
+
+// Number of RNG values the naming seed search checks past each seed (same as the naming seed search program)
+const NAMING_SEARCH_RANGE: u32 = 30_000;
+
+// Most RNG values that rng dump prints at once (about 11MB of JSON)
+const MAX_RNG_DUMP_COUNT: usize = 1_000_000;
+
+// How long the mock client waits for messages from the tool after its script finishes, by default
+const MOCK_CLIENT_LINGER_MS: u64 = 500;
+
+// How often to check whether a search has finished
+const SEARCH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Options given after a subcommand, as "--name value" pairs (or just "--name", for flags)
+struct Options<'a> {
+    values: HashMap<&'a str, &'a str>,
+    flags: Vec<&'a str>
+}
+impl<'a> Options<'a> {
+    // Parses options, only allowing the given names (along with the ones common to every subcommand)
+    fn parse(args: &'a [String], value_names: &[&str], flag_names: &[&str]) -> Result<Self, String> {
+        let mut options = Options { values: HashMap::new(), flags: Vec::new() };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let name = arg.strip_prefix("--").ok_or_else(|| format!("Unexpected argument \"{}\"", arg))?;
+            if flag_names.contains(&name) {
+                options.flags.push(name);
+            } else if value_names.contains(&name) || name == "profile" {
+                let value = args.next().ok_or_else(|| format!("Missing value for --{}", name))?;
+                options.values.insert(name, value);
+            } else {
+                return Err(format!("Unknown option --{}", name));
+            }
+        }
+        Ok(options)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.contains(&name)
+    }
+
+    fn get(&self, name: &str) -> Option<&'a str> {
+        self.values.get(name).copied()
+    }
+
+    fn require(&self, name: &str) -> Result<&'a str, String> {
+        self.get(name).ok_or_else(|| format!("Missing required option --{}", name))
+    }
+
+    fn parse_value<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        self.get(name).map(|value| value.parse().map_err(|_| format!("Invalid value \"{}\" for --{}", value, name))).transpose()
+    }
+
+    fn require_value<T: FromStr>(&self, name: &str) -> Result<T, String> {
+        self.parse_value(name)?.ok_or_else(|| format!("Missing required option --{}", name))
+    }
+}
+
+const SEARCH_OPTIONS: [&str; 2] = ["backend", "max-candidates"];
+
+// Loads the config (with the chosen profile, if any)
+fn load_config(options: &Options) -> Result<Config, String> {
+    Config::read(options.get("profile")).map_err(|problems| {
+        let problems: Vec<String> = problems.iter().map(|problem| problem.to_string()).collect();
+        format!("config.json has problems: {}", problems.join(" "))
+    })
+}
+
+fn choose_backend(options: &Options, config: &Config) -> Result<ConfigComputeBackend, String> {
+    match options.get("backend") {
+        None => Ok(config.compute_backend),
+        Some("auto") => Ok(ConfigComputeBackend::Auto),
+        Some("gpu") => Ok(ConfigComputeBackend::Gpu),
+        Some("cpu") => Ok(ConfigComputeBackend::Cpu),
+        Some(backend) => Err(format!("Invalid value \"{}\" for --backend (expected auto, gpu or cpu)", backend))
+    }
+}
+
+fn max_candidates(options: &Options, config: &Config) -> Result<u32, String> {
+    let max_candidates = options.parse_value("max-candidates")?.unwrap_or(config.search_max_candidates);
+    compute_backend::validate_max_candidates(max_candidates)?;
+    Ok(max_candidates)
+}
+
+// Parses points in the form "x,y;x,y;..."
+fn parse_points(points: &str) -> Result<Vec<PointU32>, String> {
+    points.split(';').filter(|point| !point.trim().is_empty()).map(|point| {
+        let (x, y) = point.split_once(',').ok_or_else(|| format!("Invalid point \"{}\" (expected x,y)", point))?;
+        let x: i16 = x.trim().parse().map_err(|_| format!("Invalid point \"{}\"", point))?;
+        let y: i16 = y.trim().parse().map_err(|_| format!("Invalid point \"{}\"", point))?;
+        Ok(PointU32::new(x, y))
+    }).collect()
+}
+
+// Finds a battlegroup by name, ignoring case, spaces and punctuation (e.g. "knight knight, madjick")
+fn find_battlegroup(name: &str) -> Result<Battlegroup, String> {
+    let normalize = |name: &str| name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect::<String>();
+    let normalized_name = normalize(name);
+    Battlegroup::ALL.into_iter().find(|battlegroup| normalize(battlegroup.get_name()) == normalized_name).ok_or_else(|| {
+        let names: Vec<String> = Battlegroup::ALL.iter().map(|battlegroup| battlegroup.get_name().replace(",\n", ", ")).collect();
+        format!("Unknown battlegroup \"{}\" (expected one of: {})", name, names.join("; "))
+    })
+}
+
+// Runs a single search on a compute thread, blocking until it finishes
+fn run_search<P: Send + 'static, R: Default + Send + 'static, F>(params: P, initial_params: P, thread_func: F) -> Result<R, String>
+        where F: FnOnce(compute_backend::SearchJobHandle<P, R>) + Send + 'static {
+    let mut compute_job = SearchJob::spawn(initial_params, thread_func);
+    compute_job.begin_search(params);
+    loop {
+        if let Some(mut result) = compute_job.poll_result() {
+            return Ok(std::mem::take(&mut *result));
+        }
+        if let Some(e) = compute_job.poll_error() {
+            return Err(format!("Search failed: {}", e));
+        }
+        thread::sleep(SEARCH_POLL_INTERVAL);
+    }
+}
+
+fn naming_search(args: &[String]) -> Result<Value, String> {
+    let options = Options::parse(args, &[&SEARCH_OPTIONS[..], &["pixels"]].concat(), &[])?;
+    let config = load_config(&options)?;
+
+    let matching_pixels: Vec<bool> = options.require("pixels")?.chars().map(|c| match c {
+        '0' => Ok(false),
+        '1' => Ok(true),
+        _ => Err(format!("Invalid character '{}' in --pixels (expected only 0 and 1)", c))
+    }).collect::<Result<_, _>>()?;
+    if matching_pixels.len() != 104 {
+        return Err(format!("Expected 104 pixels, but got {}", matching_pixels.len()));
+    }
+
+    let runner_version = config.runner_version;
+    let unique_seeds = Arc::new(Mutex::new(rng_cache::load_unique_seeds(&runner_version)));
+    let compute_backend = choose_backend(&options, &config)?;
+    let result: NamingSearchResult = run_search(NamingSearchParameters {
+        search_range: NAMING_SEARCH_RANGE,
+        max_candidates: max_candidates(&options, &config)?,
+        rng_15bit: runner_version.rng_15bit(),
+        rng_signed: runner_version.rng_signed(),
+        rng_old_poly: runner_version.rng_old_poly(),
+        matching_pixels,
+        seed_subset: None
+    }, NamingSearchParameters {
+        search_range: 0,
+        max_candidates: 0,
+        matching_pixels: vec![],
+        rng_15bit: false,
+        rng_old_poly: false,
+        rng_signed: false,
+        seed_subset: None
+    }, move |job| {
+        compute_naming_search::thread_func(job, unique_seeds, compute_backend);
+    })?;
+
+    let candidates: Vec<Value> = result.candidates.iter().map(|candidate| json!({
+        "seed": candidate.seed,
+        "position": candidate.position
+    })).collect();
+    Ok(json!({
+        "match_count": result.match_count,
+        "candidates": candidates
+    }))
+}
+
+fn snowball_search(args: &[String]) -> Result<Value, String> {
+    let options = Options::parse(args, &[&SEARCH_OPTIONS[..], &["seed", "pos", "points", "mode", "range"]].concat(), &[])?;
+    let config = load_config(&options)?;
+
+    let seed: u32 = options.require_value("seed")?;
+    let position: usize = options.require_value("pos")?;
+    let search_mode = match options.get("mode") {
+        None | Some("exact") => SnowballSearchMode::Exact,
+        Some("scored") => SnowballSearchMode::Scored,
+        Some(mode) => return Err(format!("Invalid value \"{}\" for --mode (expected exact or scored)", mode))
+    };
+    let search_range: u32 = options.parse_value("range")?.unwrap_or(config.snowball_search_range);
+    if search_range == 0 {
+        return Err("--range must be at least 1".to_string());
+    }
+    if search_range > compute_snowball_search::MAX_SEARCH_RANGE {
+        return Err(format!("--range is {}, which would use too much memory (the maximum is {})", search_range, compute_snowball_search::MAX_SEARCH_RANGE));
+    }
+    let params = SnowballSearchParameters {
+        search_mode,
+        search_range,
+        max_candidates: max_candidates(&options, &config)?,
+        matching_snowballs: parse_points(options.require("points")?)?
+    };
+    compute_snowball_search::validate_parameters(&params)?;
+
+    // Same starting position as the Dogi manip program
+    let runner_version = config.runner_version;
+    let start_position = position + config.snowball_search_start_offset as usize;
+    let mut rng = RNG::new(seed, runner_version.rng_15bit(), runner_version.rng_signed(), runner_version.rng_old_poly());
+    rng.skip(start_position);
+    let compute_backend = choose_backend(&options, &config)?;
+    let result: SnowballSearchResult = run_search(params, SnowballSearchParameters {
+        search_mode: SnowballSearchMode::Exact,
+        search_range: 0,
+        max_candidates: 0,
+        matching_snowballs: vec![]
+    }, move |job| {
+        compute_snowball_search::thread_func(&[rng], search_range as usize, job, Arc::new(AtomicBool::new(false)), compute_backend);
+    })?;
+
+    let scored_positions: Vec<Value> = result.scored_positions.iter().map(|scored| json!({
+        "position": scored.position,
+        "total_error": scored.total_error,
+        "max_error": scored.max_error,
+        "outlier_index": scored.outlier_index
+    })).collect();
+    Ok(json!({
+        "start_position": start_position,
+        "match_count": result.match_count,
+        "matched_positions": result.matched_positions,
+        "scored_positions": scored_positions
+    }))
+}
+
+fn dust_search(args: &[String]) -> Result<Value, String> {
+    let options = Options::parse(args, &[&SEARCH_OPTIONS[..], &["seed", "pos", "battlegroup", "points", "attacks"]].concat(), &["early", "level-up"])?;
+    let config = load_config(&options)?;
+
+    let seed: u32 = options.require_value("seed")?;
+    let position: usize = options.require_value("pos")?;
+    let battlegroup = find_battlegroup(options.require("battlegroup")?)?;
+    let num_attacks: i32 = options.parse_value("attacks")?.unwrap_or(1);
+    if num_attacks < 1 {
+        return Err("--attacks must be at least 1".to_string());
+    }
+    let search_config = battlegroup.get_dust_config();
+    let search_mode = match options.flag("early") {
+        true => search_config.dust_data.search_mode.to_early(),
+        false => search_config.dust_data.search_mode
+    };
+    let text_length = match options.flag("level-up") {
+        true => search_config.text_length_lvup,
+        false => search_config.text_length
+    };
+    let (params, search_anim) = compute_dust_search::create_search_parameters(&search_config, search_mode, num_attacks,
+        parse_points(options.require("points")?)?, compute_dust_search::DUST_SEARCH_RNG_COUNT, max_candidates(&options, &config)?);
+    compute_dust_search::validate_parameters(&params)?;
+
+    let prng = Arc::new(rng_cache::load_precomputed_rng(&config.runner_version, seed, position, compute_dust_search::DUST_SEARCH_RNG_COUNT));
+    let compute_backend = choose_backend(&options, &config)?;
+    let result: DustSearchResult = run_search(params, DustSearchParameters {
+        search_mode: DustSearchMode::LastFrame,
+        search_range: 0,
+        max_candidates: 0,
+        last_frame_rng_offset: 0,
+        last_frame_particle_count: 0,
+        second_last_frame_particle_count: 0,
+        initial_rng_skip_amount: 0,
+        matching_particles: vec![],
+        initial_particles: vec![],
+    }, move |job| {
+        compute_dust_search::thread_func(job, prng, compute_backend);
+    })?;
+
+    let predicted_positions: Vec<usize> = result.matched_positions.iter()
+        .map(|&matched_position| matched_position as usize + search_anim.get_after_battle_rng_calls(text_length))
+        .collect();
+    Ok(json!({
+        "start_position": position,
+        "match_count": result.match_count,
+        "matched_positions": result.matched_positions,
+        "predicted_positions": predicted_positions
+    }))
+}
+
+fn rng_dump(args: &[String]) -> Result<Value, String> {
+    let options = Options::parse(args, &["seed", "from", "count"], &[])?;
+    let config = load_config(&options)?;
+
+    let seed: u32 = options.require_value("seed")?;
+    let from: usize = options.require_value("from")?;
+    let count: usize = options.require_value("count")?;
+    if count > MAX_RNG_DUMP_COUNT {
+        return Err(format!("--count is {}, but at most {} values can be dumped at once", count, MAX_RNG_DUMP_COUNT));
+    }
+
+    let runner_version = config.runner_version;
+    let mut rng = RNG::new(seed, runner_version.rng_15bit(), runner_version.rng_signed(), runner_version.rng_old_poly());
+    rng.skip(from);
+    let values: Vec<u32> = (0..count).map(|_| rng.next_u32()).collect();
+    Ok(json!({
+        "seed": seed,
+        "from": from,
+        "values": values
+    }))
+}
+
+fn mock_client(args: &[String]) -> Result<Value, String> {
+    let options = Options::parse(args, &["script", "port", "linger"], &[])?;
+    let port: u16 = match options.parse_value("port")? {
+        Some(port) => port,
+        None => load_config(&options)?.server_port
+    };
+    let linger: u64 = options.parse_value("linger")?.unwrap_or(MOCK_CLIENT_LINGER_MS);
+    let steps = mock_client::load_script(Path::new(options.require("script")?))?;
+
+    let mut client = MockClient::connect(port)?;
+    for step in &steps {
+        client.run_step(step)?;
+        client.poll_received();
+    }
+    let received: Vec<Value> = client.finish(Duration::from_millis(linger))?.iter().map(|message| message.to_json()).collect();
+    Ok(json!({
+        "port": port,
+        "steps": steps.len(),
+        "received": received
+    }))
+}
+
+// Makes output visible when run from a console on Windows (as release builds don't get one of their own)
+fn attach_console() {
+    if cfg!(windows) {
+        unsafe { windows_sys::Win32::System::Console::AttachConsole(windows_sys::Win32::System::Console::ATTACH_PARENT_PROCESS) };
+    }
+}
+
+// Runs the subcommand given on the command line, returning the exit code for the process.
+// Returns None when there's no subcommand (only options for the GUI, if anything), in which case the GUI should launch.
+pub fn run(args: &[String]) -> Option<i32> {
+    let subcommand = args.get(1).filter(|arg| !arg.starts_with("--"))?;
+    attach_console();
+
+    let result = match subcommand.as_str() {
+        "naming-search" => naming_search(&args[2..]),
+        "snowball-search" => snowball_search(&args[2..]),
+        "dust-search" => dust_search(&args[2..]),
+        "mock-client" => mock_client(&args[2..]),
+        "rng" if args.get(2).map(String::as_str) == Some("dump") => rng_dump(&args[3..]),
+        "help" => {
+            println!("{}", USAGE);
+            return Some(0);
+        },
+        _ => Err(format!("Unknown subcommand \"{}\"", args[1..].join(" ")))
+    };
+    match result {
+        Ok(output) => {
+            println!("{}", serde_json::to_string_pretty(&output).unwrap());
+            Some(0)
+        },
+        Err(e) => {
+            println!("{}", json!({ "error": e }));
+            eprintln!("{}", USAGE);
+            Some(1)
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, MutexGuard}, thread::{self, JoinHandle}, time::Instant};
+
+use crate::config::ConfigComputeBackend;
+
+// Common interface for performing searches, implemented for both the GPU and the CPU
+pub trait SearchBackend {
+    type Parameters;
+    type Result;
+
+    // Performs a single search with the given parameters, blocking until finished
+    fn search(&mut self, params: &Self::Parameters) -> Result<Self::Result, &'static str>;
+
+    // Frees any resources held by this backend
+    fn unload(&mut self) {}
+}
+
+pub type BoxedSearchBackend<P, R> = Box<dyn SearchBackend<Parameters = P, Result = R>>;
+
+// Upper limit on the number of candidates a single search can return (used to size GPU output buffers)
+pub const MAX_SEARCH_CANDIDATES: u32 = 64;
+
+pub fn validate_max_candidates(max_candidates: u32) -> Result<(), &'static str> {
+    if max_candidates == 0 {
+        return Err("Need to request at least one candidate");
+    }
+    if max_candidates > MAX_SEARCH_CANDIDATES {
+        return Err("Too many candidates requested (max is 64)");
+    }
+    Ok(())
+}
+
+// Chooses and preloads a search backend, as configured.
+// In automatic mode, the CPU is used whenever the GPU backend fails to preload.
+pub fn preload_backend<D, P, R>(choice: ConfigComputeBackend, data: D,
+                                preload_gpu: fn(&D) -> Result<BoxedSearchBackend<P, R>, &'static str>,
+                                preload_cpu: fn(D) -> BoxedSearchBackend<P, R>) -> Result<BoxedSearchBackend<P, R>, &'static str> {
+    match choice {
+        ConfigComputeBackend::Auto => match preload_gpu(&data) {
+            Ok(backend) => Ok(backend),
+            Err(e) => {
+                eprintln!("Failed to preload GPU search ({}), falling back to CPU", e);
+                Ok(preload_cpu(data))
+            }
+        },
+        ConfigComputeBackend::Gpu => preload_gpu(&data),
+        ConfigComputeBackend::Cpu => Ok(preload_cpu(data))
+    }
+}
+
+// Thread-side half of a search job, used by compute threads to receive search requests and send back results
+pub struct SearchJobHandle<P, R> {
+    end_thread: Arc<AtomicBool>,
+    perform_search: Arc<AtomicBool>,
+    parameters: Arc<Mutex<P>>,
+    output: Arc<Mutex<R>>,
+    error: Arc<Mutex<Option<&'static str>>>
+}
+impl<P, R: Default> SearchJobHandle<P, R> {
+    // Whether the thread has been asked to end (e.g. to stop preloading early)
+    pub fn should_end(&self) -> bool {
+        self.end_thread.load(Ordering::Relaxed)
+    }
+
+    // Reports an error that stops the compute thread from performing any searches (the thread should end right after)
+    pub fn fail(&self, error: &'static str) {
+        eprintln!("Compute thread failed: {}", error);
+        *self.error.lock().unwrap() = Some(error);
+    }
+
+    // Services search requests using the given backend, until the job is cancelled, then unloads the backend
+    pub fn run_search_loop(&self, backend: &mut BoxedSearchBackend<P, R>) {
+        loop {
+            // Wait until an end thread or perform search signal are sent
+            let mut end_thread_signal = false;
+            let mut perform_search_signal = false;
+            loop {
+                if self.end_thread.load(Ordering::Relaxed) {
+                    self.end_thread.store(false, Ordering::Relaxed);
+                    end_thread_signal = true;
+                    break;
+                }
+                if self.perform_search.load(Ordering::Relaxed) {
+                    perform_search_signal = true;
+                    break;
+                }
+                thread::park();
+            }
+            if end_thread_signal {
+                break;
+            }
+            if !perform_search_signal {
+                continue;
+            }
+
+            let now = Instant::now();
+
+            // Begin search with current parameters
+            let params = self.parameters.lock().unwrap();
+            let search_result = match backend.search(&params) {
+                Ok(result) => result,
+                Err(e) => {
+                    eprintln!("Error occurred during search: {}", e);
+                    R::default()
+                }
+            };
+            drop(params);
+
+            let elapsed = now.elapsed();
+            eprintln!("Elapsed: {:.2?}", elapsed);
+
+            // Output results
+            *self.output.lock().unwrap() = search_result;
+
+            // Allow new searches to be performed
+            self.perform_search.store(false, Ordering::Relaxed);
+        }
+
+        backend.unload();
+    }
+}
+
+// Program-side half of a search job, which owns the compute thread and ends it when dropped
+pub struct SearchJob<P, R> {
+    end_thread: Arc<AtomicBool>,
+    perform_search: Arc<AtomicBool>,
+    parameters: Arc<Mutex<P>>,
+    output: Arc<Mutex<R>>,
+    error: Arc<Mutex<Option<&'static str>>>,
+    join_handle: JoinHandle<()>,
+    waiting_for_result: bool
+}
+impl<P: Send + 'static, R: Default + Send + 'static> SearchJob<P, R> {
+    pub fn spawn<F>(initial_parameters: P, thread_func: F) -> Self where F: FnOnce(SearchJobHandle<P, R>) + Send + 'static {
+        let end_thread = Arc::new(AtomicBool::new(false));
+        let perform_search = Arc::new(AtomicBool::new(false));
+        let parameters = Arc::new(Mutex::new(initial_parameters));
+        let output = Arc::new(Mutex::new(R::default()));
+        let error = Arc::new(Mutex::new(None));
+        let handle = SearchJobHandle {
+            end_thread: end_thread.clone(),
+            perform_search: perform_search.clone(),
+            parameters: parameters.clone(),
+            output: output.clone(),
+            error: error.clone()
+        };
+        let join_handle = thread::spawn(move || thread_func(handle));
+        SearchJob {
+            end_thread,
+            perform_search,
+            parameters,
+            output,
+            error,
+            join_handle,
+            waiting_for_result: false
+        }
+    }
+
+    // Queues up a new search on the compute thread
+    pub fn begin_search(&mut self, params: P) {
+        *self.parameters.lock().unwrap() = params;
+        self.waiting_for_result = true;
+        self.perform_search.store(true, Ordering::Relaxed);
+        self.join_handle.thread().unpark();
+    }
+
+    // Whether a search has been started, and its result hasn't been retrieved yet
+    pub fn is_searching(&self) -> bool {
+        self.waiting_for_result
+    }
+
+    // Returns the result of the latest search, once (and only once) it has finished
+    pub fn poll_result(&mut self) -> Option<MutexGuard<'_, R>> {
+        if !self.waiting_for_result || self.perform_search.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.waiting_for_result = false;
+        Some(self.output.lock().unwrap())
+    }
+
+    // Whether the compute thread has stopped running (e.g. after failing to preload), meaning no more results will arrive
+    pub fn has_ended(&self) -> bool {
+        self.join_handle.is_finished()
+    }
+
+    // Returns why the latest search won't get a result, if the compute thread has stopped running (e.g. after failing to
+    // preload the configured backend). The search is no longer considered in progress after this.
+    pub fn poll_error(&mut self) -> Option<&'static str> {
+        if !self.waiting_for_result || !self.has_ended() {
+            return None;
+        }
+        self.waiting_for_result = false;
+        Some(self.error.lock().unwrap().unwrap_or("Compute thread ended unexpectedly"))
+    }
+}
+impl<P, R> SearchJob<P, R> {
+    // Ends the compute thread (including any preloading in progress), unloading its backend
+    pub fn cancel(&self) {
+        self.end_thread.store(true, Ordering::Relaxed);
+        self.join_handle.thread().unpark();
+    }
+}
+impl<P, R> Drop for SearchJob<P, R> {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn reports_failed_compute_threads() {
+        let mut compute_job: SearchJob<u32, u32> = SearchJob::spawn(0, |job| job.fail("No GPU available"));
+        assert_eq!(compute_job.poll_error(), None);
+        compute_job.begin_search(1);
+        while !compute_job.has_ended() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(compute_job.poll_result().is_none());
+        assert_eq!(compute_job.poll_error(), Some("No GPU available"));
+        assert!(!compute_job.is_searching());
+        assert_eq!(compute_job.poll_error(), None);
+    }
+}
@@ -1,8 +1,9 @@
-use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread, time::Instant};
+use std::sync::Arc;
 
+use rayon::prelude::*;
 use sdl3::sys::gpu::{SDL_GPUComputePipeline, SDL_GPUDevice, SDL_GPUFence};
 
-use crate::{compute_shaders::{self, ComputePipelineInfo, GPUBufferInfo, PointU32}, rng::PrecomputedRNG};
+use crate::{compute_backend::{self, BoxedSearchBackend, SearchBackend, SearchJobHandle, MAX_SEARCH_CANDIDATES}, compute_shaders::{self, ComputePipelineInfo, GPUBufferInfo, PointU32}, config::ConfigComputeBackend, dust::{DustAnimation, DustSearchConfig}, rng::PrecomputedRNG};
 
 struct DustLastFrameComputeData {
     gpu_device: *mut SDL_GPUDevice,
@@ -17,12 +18,18 @@ struct DustLastFrameComputeData {
     preload_fence: *mut SDL_GPUFence
 }
 
+struct DustCpuSearch {
+    prng: Arc<PrecomputedRNG>
+}
+
+#[derive(Default)]
 pub struct DustSearchResult {
     pub match_count: u32,
-    pub single_matched_position: u32
+    // Up to the requested maximum number of matched positions, sorted in ascending order
+    pub matched_positions: Vec<u32>
 }
 
-fn preload(prng: &PrecomputedRNG) -> Result<DustLastFrameComputeData, &'static str> {
+fn preload(prng: &Arc<PrecomputedRNG>) -> Result<BoxedSearchBackend<DustSearchParameters, DustSearchResult>, &'static str> {
     // Create device and pipelines based on shaders
     let device = compute_shaders::create_gpu_device()?;
     let pipeline_last_frame = compute_shaders::create_compute_pipeline(device, &ComputePipelineInfo {
@@ -59,7 +66,7 @@ fn preload(prng: &PrecomputedRNG) -> Result<DustLastFrameComputeData, &'static s
     })?;
 
     // Create GPU buffers
-    let return_val_buffer = compute_shaders::create_gpu_buffer(device, 16, false, true)?;
+    let return_val_buffer = compute_shaders::create_gpu_buffer(device, 4 + (MAX_SEARCH_CANDIDATES as usize * 4), false, true)?;
     let rng_buffer = compute_shaders::create_gpu_buffer(device, prng.raw().len() * 4, true, false)?;
     let match_positions_buffer = compute_shaders::create_gpu_buffer(device, 32 * 4, true, false)?;
     let initial_particles_buffer = compute_shaders::create_gpu_buffer(device, 32 * 4, true, false)?;
@@ -71,7 +78,7 @@ fn preload(prng: &PrecomputedRNG) -> Result<DustLastFrameComputeData, &'static s
     compute_shaders::end_copy_pass(copy_pass);
     let preload_fence = compute_shaders::end_command_buffer_and_get_fence(command_buffer)?;
 
-    Ok(DustLastFrameComputeData { 
+    Ok(Box::new(DustLastFrameComputeData { 
         gpu_device: device,
         pipeline_last_frame,
         pipeline_last_frame_early,
@@ -82,7 +89,11 @@ fn preload(prng: &PrecomputedRNG) -> Result<DustLastFrameComputeData, &'static s
         match_positions_buffer,
         initial_particles_buffer,
         preload_fence
-    })
+    }))
+}
+
+fn preload_cpu(prng: Arc<PrecomputedRNG>) -> BoxedSearchBackend<DustSearchParameters, DustSearchResult> {
+    Box::new(DustCpuSearch { prng })
 }
 
 fn points_to_bytes(points: &[PointU32]) -> &[u8] {
@@ -91,18 +102,7 @@ fn points_to_bytes(points: &[PointU32]) -> &[u8] {
     unsafe { std::slice::from_raw_parts(ptr, len) }
 }
 
-fn search(data: &mut DustLastFrameComputeData, params: &DustSearchParameters) -> Result<DustSearchResult, &'static str> {
-    // If there's a preload fence to wait for and/or release, do so
-    if !data.preload_fence.is_null() {
-        let success = compute_shaders::wait_for_and_release_fences(data.gpu_device, &[data.preload_fence]);
-        data.preload_fence = std::ptr::null_mut() as *mut SDL_GPUFence;
-
-        if !success {
-            return Err("Preload command buffer was unsuccessful");
-        }
-    }
-
-    // Verify number of points are valid
+pub fn validate_parameters(params: &DustSearchParameters) -> Result<(), &'static str> {
     if params.matching_particles.len() == 0 {
         return Err("No match points");
     }
@@ -118,79 +118,211 @@ fn search(data: &mut DustLastFrameComputeData, params: &DustSearchParameters) ->
     if (params.last_frame_particle_count + params.second_last_frame_particle_count) as usize != params.initial_particles.len() {
         return Err("Frame particle counts don't sum to total");
     }
+    compute_backend::validate_max_candidates(params.max_candidates)?;
 
-    // Create command buffer for all operations
-    let command_buffer = compute_shaders::begin_command_buffer(data.gpu_device)?;
+    Ok(())
+}
 
-    // Copy search data to GPU buffers
-    let copy_pass = compute_shaders::begin_copy_pass(command_buffer)?;
-    compute_shaders::upload_to_gpu_buffer(data.gpu_device, copy_pass, &data.match_positions_buffer, points_to_bytes(&params.matching_particles))?;
-    compute_shaders::upload_to_gpu_buffer(data.gpu_device, copy_pass, &data.initial_particles_buffer, points_to_bytes(&params.initial_particles))?;
-    compute_shaders::upload_to_gpu_buffer(data.gpu_device, copy_pass, &data.return_val_buffer, &[0; 8])?;
-    compute_shaders::end_copy_pass(copy_pass);
+impl SearchBackend for DustLastFrameComputeData {
+    type Parameters = DustSearchParameters;
+    type Result = DustSearchResult;
 
-    // Push uniform data
-    let uniform_data = match params.search_mode {
-        DustSearchMode::LastFrame | DustSearchMode::LastFrameEarly => [
-            u32::to_ne_bytes(params.last_frame_particle_count), 
-            u32::to_ne_bytes(params.matching_particles.len() as u32), 
-            u32::to_ne_bytes(params.last_frame_rng_offset)
-        ].concat(),
-        DustSearchMode::SecondToLastFrame | DustSearchMode::SecondToLastFrameEarly => [
-            u32::to_ne_bytes(params.last_frame_particle_count), 
-            u32::to_ne_bytes(params.second_last_frame_particle_count), 
-            u32::to_ne_bytes(params.matching_particles.len() as u32), 
-            u32::to_ne_bytes(params.last_frame_rng_offset),
-            u32::to_ne_bytes(params.initial_rng_skip_amount)
-        ].concat()
-    };
-    compute_shaders::push_uniform_data(command_buffer, 0, &uniform_data);
-
-    // Run main search operation
-    let pipeline = match params.search_mode {
-        DustSearchMode::LastFrame => data.pipeline_last_frame,
-        DustSearchMode::LastFrameEarly => data.pipeline_last_frame_early,
-        DustSearchMode::SecondToLastFrame => data.pipeline_second_last_frame,
-        DustSearchMode::SecondToLastFrameEarly => data.pipeline_second_last_frame_early,
-    };
-    let writeable_buffer_storage = [data.return_val_buffer.raw()];
-    let all_buffer_storage_ordered = [data.rng_buffer.raw(), data.initial_particles_buffer.raw(), data.match_positions_buffer.raw()];
-    compute_shaders::perform_buffer_compute(command_buffer, pipeline, &writeable_buffer_storage, &all_buffer_storage_ordered, params.search_range / 64, 1, 1)?;
+    fn search(&mut self, params: &DustSearchParameters) -> Result<DustSearchResult, &'static str> {
+        // If there's a preload fence to wait for and/or release, do so
+        if !self.preload_fence.is_null() {
+            let success = compute_shaders::wait_for_and_release_fences(self.gpu_device, &[self.preload_fence]);
+            self.preload_fence = std::ptr::null_mut() as *mut SDL_GPUFence;
 
-    // Start download of data from return value buffer
-    let copy_pass = compute_shaders::begin_copy_pass(command_buffer)?;
-    let download_transfer_buffer = compute_shaders::queue_download_from_gpu_buffer(data.gpu_device, copy_pass, &data.return_val_buffer)?;
-    compute_shaders::end_copy_pass(copy_pass);
+            if !success {
+                return Err("Preload command buffer was unsuccessful");
+            }
+        }
 
-    // End command buffer and wait for it to finish
-    let success = compute_shaders::end_command_buffer_and_wait_for_fence(data.gpu_device, command_buffer);
-    if !success {
-        return Err("Search command buffer was unsuccessful");
-    }
+        // Verify number of points are valid
+        validate_parameters(params)?;
+
+        // Create command buffer for all operations
+        let command_buffer = compute_shaders::begin_command_buffer(self.gpu_device)?;
+
+        // Copy search data to GPU buffers
+        let copy_pass = compute_shaders::begin_copy_pass(command_buffer)?;
+        compute_shaders::upload_to_gpu_buffer(self.gpu_device, copy_pass, &self.match_positions_buffer, points_to_bytes(&params.matching_particles))?;
+        compute_shaders::upload_to_gpu_buffer(self.gpu_device, copy_pass, &self.initial_particles_buffer, points_to_bytes(&params.initial_particles))?;
+        compute_shaders::upload_to_gpu_buffer(self.gpu_device, copy_pass, &self.return_val_buffer, &[0; 4])?;
+        compute_shaders::end_copy_pass(copy_pass);
+
+        // Push uniform data
+        let uniform_data = match params.search_mode {
+            DustSearchMode::LastFrame | DustSearchMode::LastFrameEarly => [
+                u32::to_ne_bytes(params.last_frame_particle_count), 
+                u32::to_ne_bytes(params.matching_particles.len() as u32), 
+                u32::to_ne_bytes(params.last_frame_rng_offset),
+                u32::to_ne_bytes(params.max_candidates)
+            ].concat(),
+            DustSearchMode::SecondToLastFrame | DustSearchMode::SecondToLastFrameEarly => [
+                u32::to_ne_bytes(params.last_frame_particle_count), 
+                u32::to_ne_bytes(params.second_last_frame_particle_count), 
+                u32::to_ne_bytes(params.matching_particles.len() as u32), 
+                u32::to_ne_bytes(params.last_frame_rng_offset),
+                u32::to_ne_bytes(params.initial_rng_skip_amount),
+                u32::to_ne_bytes(params.max_candidates)
+            ].concat()
+        };
+        compute_shaders::push_uniform_data(command_buffer, 0, &uniform_data);
+
+        // Run main search operation
+        let pipeline = match params.search_mode {
+            DustSearchMode::LastFrame => self.pipeline_last_frame,
+            DustSearchMode::LastFrameEarly => self.pipeline_last_frame_early,
+            DustSearchMode::SecondToLastFrame => self.pipeline_second_last_frame,
+            DustSearchMode::SecondToLastFrameEarly => self.pipeline_second_last_frame_early,
+        };
+        let writeable_buffer_storage = [self.return_val_buffer.raw()];
+        let all_buffer_storage_ordered = [self.rng_buffer.raw(), self.initial_particles_buffer.raw(), self.match_positions_buffer.raw()];
+        compute_shaders::perform_buffer_compute(command_buffer, pipeline, &writeable_buffer_storage, &all_buffer_storage_ordered, params.search_range / 64, 1, 1)?;
+
+        // Start download of data from return value buffer
+        let copy_pass = compute_shaders::begin_copy_pass(command_buffer)?;
+        let download_transfer_buffer = compute_shaders::queue_download_from_gpu_buffer(self.gpu_device, copy_pass, &self.return_val_buffer)?;
+        compute_shaders::end_copy_pass(copy_pass);
 
-    // Finish download of data from return value buffer
-    let mut output_buffer: Vec<u8> = vec![0; 8];
-    compute_shaders::finish_download_from_gpu_buffer(data.gpu_device, &data.return_val_buffer, download_transfer_buffer, &mut output_buffer)?;
+        // End command buffer and wait for it to finish
+        let success = compute_shaders::end_command_buffer_and_wait_for_fence(self.gpu_device, command_buffer);
+        if !success {
+            return Err("Search command buffer was unsuccessful");
+        }
+
+        // Finish download of data from return value buffer
+        let mut output_buffer: Vec<u8> = vec![0; 4 + (params.max_candidates as usize * 4)];
+        compute_shaders::finish_download_from_gpu_buffer(self.gpu_device, &self.return_val_buffer, download_transfer_buffer, &mut output_buffer)?;
+
+        // Interpret final data (positions are written in no particular order, so sort them)
+        let match_count = u32::from_ne_bytes(output_buffer[0..4].try_into().unwrap());
+        let mut matched_positions: Vec<u32> = output_buffer[4..].chunks_exact(4)
+            .take(u32::min(match_count, params.max_candidates) as usize)
+            .map(|position| u32::from_ne_bytes(position.try_into().unwrap()))
+            .collect();
+        matched_positions.sort();
+        Ok(DustSearchResult {
+            match_count,
+            matched_positions
+        })
+    }
 
-    // Interpret final data
-    Ok(DustSearchResult {
-        match_count: u32::from_ne_bytes(output_buffer[0..4].try_into().unwrap()),
-        single_matched_position: u32::from_ne_bytes(output_buffer[4..8].try_into().unwrap())
-    })
+    fn unload(&mut self) {
+        compute_shaders::free_gpu_buffer(self.gpu_device, &self.initial_particles_buffer);
+        compute_shaders::free_gpu_buffer(self.gpu_device, &self.match_positions_buffer);
+        compute_shaders::free_gpu_buffer(self.gpu_device, &self.rng_buffer);
+        compute_shaders::free_gpu_buffer(self.gpu_device, &self.return_val_buffer);
+        compute_shaders::free_compute_pipeline(self.gpu_device, self.pipeline_last_frame);
+        compute_shaders::free_compute_pipeline(self.gpu_device, self.pipeline_last_frame_early);
+        compute_shaders::free_compute_pipeline(self.gpu_device, self.pipeline_second_last_frame);
+        compute_shaders::free_compute_pipeline(self.gpu_device, self.pipeline_second_last_frame_early);
+        compute_shaders::free_gpu_device(self.gpu_device);
+    }
 }
 
 // TODO: function to replace RNG sequence with a different one (e.g. to reduce search range)
 
-fn unload(data: &DustLastFrameComputeData) {
-    compute_shaders::free_gpu_buffer(data.gpu_device, &data.initial_particles_buffer);
-    compute_shaders::free_gpu_buffer(data.gpu_device, &data.match_positions_buffer);
-    compute_shaders::free_gpu_buffer(data.gpu_device, &data.rng_buffer);
-    compute_shaders::free_gpu_buffer(data.gpu_device, &data.return_val_buffer);
-    compute_shaders::free_compute_pipeline(data.gpu_device, data.pipeline_last_frame);
-    compute_shaders::free_compute_pipeline(data.gpu_device, data.pipeline_last_frame_early);
-    compute_shaders::free_compute_pipeline(data.gpu_device, data.pipeline_second_last_frame);
-    compute_shaders::free_compute_pipeline(data.gpu_device, data.pipeline_second_last_frame_early);
-    compute_shaders::free_gpu_device(data.gpu_device);
+// Rounding offset as used by Direct3D
+const ROUNDING_OFFSET: f32 = 1.0 / 512.0;
+
+// Gets the float value from a 32-bit int RNG value, with the same precision as the shaders
+fn rng_to_float(value: u32, range: f32) -> f32 {
+    (value as f32) * 2.3283064365386963e-10 * range
+}
+
+// Simulates a single frame's worth of particles on the CPU, marking off any matching positions
+fn simulate_frame_cpu(rng_values: &[u32], rng_position: usize, particles: &[PointU32], hspeed_factor: f32, gravity_factor: f32,
+                      matching_particles: &[PointU32], match_bitflag: &mut u32) {
+    for (i, particle) in particles.iter().enumerate() {
+        let mut px = particle.x() as u16 as f32;
+        let mut py = particle.y() as u16 as f32;
+
+        // Use PRNG to simulate hspeed/gravity over time
+        let gravity = rng_to_float(rng_values[rng_position + (i * 2)], 0.5) + 0.2;
+        let hspeed = rng_to_float(rng_values[rng_position + (i * 2) + 1], 4.0) - 2.0;
+        px += hspeed * hspeed_factor;
+        py -= gravity * gravity_factor;
+
+        // Round final positions as they are displayed on-screen
+        let rpx = f32::round_ties_even(px - ROUNDING_OFFSET) as i32;
+        let rpy = f32::round_ties_even(py - ROUNDING_OFFSET) as i32;
+
+        // Check if any positions match this particle
+        for (j, matching_particle) in matching_particles.iter().enumerate() {
+            if (*match_bitflag & (1 << j)) == 0 &&
+               rpx == matching_particle.x() as u16 as i32 && rpy == matching_particle.y() as u16 as i32 {
+                *match_bitflag |= 1 << j;
+                break;
+            }
+        }
+    }
+}
+
+// Checks a single RNG position on the CPU, equivalent to a single invocation of the dust shaders
+fn matches_position_cpu(rng_values: &[u32], start_rng_position: usize, params: &DustSearchParameters) -> bool {
+    if start_rng_position < params.last_frame_rng_offset as usize {
+        // Skip if this is too early in the RNG sequence
+        return false;
+    }
+
+    // Speed factors depend on how many frames the particles have been moving for
+    let (last_frame_factors, second_last_frame_factors) = match params.search_mode {
+        DustSearchMode::LastFrame => ((11.0, 66.0), (0.0, 0.0)),
+        DustSearchMode::LastFrameEarly => ((10.0, 55.0), (0.0, 0.0)),
+        DustSearchMode::SecondToLastFrame => ((10.0, 55.0), (11.0, 66.0)),
+        DustSearchMode::SecondToLastFrameEarly => ((9.0, 45.0), (10.0, 55.0))
+    };
+
+    // Simulate last frame's particles
+    let last_frame_count = params.last_frame_particle_count as usize;
+    let mut match_bitflag: u32 = 0;
+    simulate_frame_cpu(rng_values, start_rng_position, &params.initial_particles[0..last_frame_count], 
+                       last_frame_factors.0, last_frame_factors.1, &params.matching_particles, &mut match_bitflag);
+
+    // Simulate second to last frame's particles
+    match params.search_mode {
+        DustSearchMode::SecondToLastFrame | DustSearchMode::SecondToLastFrameEarly => {
+            let rng_offset = (params.second_last_frame_particle_count * 2) as usize + params.initial_rng_skip_amount as usize;
+            let Some(rng_position) = start_rng_position.checked_sub(rng_offset) else { return false };
+            simulate_frame_cpu(rng_values, rng_position, &params.initial_particles[last_frame_count..], 
+                               second_last_frame_factors.0, second_last_frame_factors.1, &params.matching_particles, &mut match_bitflag);
+        },
+        DustSearchMode::LastFrame | DustSearchMode::LastFrameEarly => {}
+    }
+
+    // Check whether all positions have been matched
+    match_bitflag == u32::MAX >> (32 - params.matching_particles.len())
+}
+
+impl SearchBackend for DustCpuSearch {
+    type Parameters = DustSearchParameters;
+    type Result = DustSearchResult;
+
+    fn search(&mut self, params: &DustSearchParameters) -> Result<DustSearchResult, &'static str> {
+        validate_parameters(params)?;
+
+        // Same range of positions as covered by the shader dispatch, which must fit within the precomputed RNG
+        let search_range = ((params.search_range / 64) * 64) as usize;
+        let rng_values = self.prng.raw();
+        if search_range + (params.last_frame_particle_count as usize * 2) > rng_values.len() {
+            return Err("Search range exceeds precomputed RNG");
+        }
+
+        // Search all positions in parallel
+        let mut matched_positions: Vec<u32> = (0..search_range).into_par_iter()
+            .filter(|&position| matches_position_cpu(rng_values, position, params))
+            .map(|position| position as u32 - params.last_frame_rng_offset)
+            .collect();
+
+        let match_count = matched_positions.len() as u32;
+        matched_positions.truncate(params.max_candidates as usize);
+        Ok(DustSearchResult {
+            match_count,
+            matched_positions
+        })
+    }
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -221,6 +353,7 @@ impl DustSearchMode {
 pub struct DustSearchParameters {
     pub search_mode: DustSearchMode,
     pub search_range: u32,
+    pub max_candidates: u32,
     pub last_frame_rng_offset: u32,
     pub initial_rng_skip_amount: u32,
     pub matching_particles: Vec<PointU32>,
@@ -229,62 +362,72 @@ pub struct DustSearchParameters {
     pub second_last_frame_particle_count: u32
 }
 
-pub fn thread_func(end_thread: Arc<AtomicBool>, perform_search: Arc<AtomicBool>,
-                   prng: Arc<PrecomputedRNG>, parameters: Arc<Mutex<DustSearchParameters>>,
-                   output: Arc<Mutex<DustSearchResult>>) {
-    println!("Dust last frame compute thread started");
-    let mut dust_last_frame_data = preload(&prng).expect("Failed to preload");
-
-    loop {
-        // Wait until an end thread or perform search signal are sent
-        let mut end_thread_signal = false;
-        let mut perform_search_signal = false;
-        loop {
-            if end_thread.load(Ordering::Relaxed) {
-                end_thread.store(false, Ordering::Relaxed);
-                end_thread_signal = true;
-                break;
-            }
-            if perform_search.load(Ordering::Relaxed) {
-                perform_search_signal = true;
-                break;
+// Number of RNG values precomputed for dust searches, starting from the minimum RNG position. This only bounds
+// the searched range (it is what gets uploaded to the GPU); predictions past a match jump ahead with RNG::at instead.
+pub const DUST_SEARCH_RNG_COUNT: usize = 500_000;
+
+// Builds the search parameters for the given dust animation (with its particles offset by the number of attacks), searching up to the given number of RNG values.
+// Also returns the animation being searched for, to predict RNG calls made after it.
+pub fn create_search_parameters(search_config: &DustSearchConfig, search_mode: DustSearchMode, num_attacks: i32,
+                                matching_particles: Vec<PointU32>, rng_count: usize, max_candidates: u32) -> (DustSearchParameters, DustAnimation) {
+    let mut initial_particles: Vec<PointU32> = Vec::with_capacity(32);
+    let mut initial_particles_last_frame_count = 0;
+    let mut initial_particles_second_last_frame_count = 0;
+
+    let mut search_anim = search_config.dust_data.create_animation();
+    search_anim.compute_frame_rng_offsets();
+    for particle in search_anim.get_frames().last().unwrap().iter() {
+        initial_particles.push(PointU32::new(particle.get_x() as i16 - (num_attacks as i16), particle.get_y() as i16));
+        initial_particles_last_frame_count += 1;
+    }
+    match search_mode {
+        DustSearchMode::SecondToLastFrame | DustSearchMode::SecondToLastFrameEarly => {
+            for particle in search_anim.get_frames().get(search_anim.get_frame_count() - 2).unwrap().iter() {
+                initial_particles.push(PointU32::new(particle.get_x() as i16 - (num_attacks as i16), particle.get_y() as i16));
+                initial_particles_second_last_frame_count += 1;
             }
-            thread::park();
-        }
-        if end_thread_signal {
-            break;
-        }
-        if !perform_search_signal {
-            continue;
         }
-        
-        let now = Instant::now();
-
-        // Begin search with current parameters
-        let params = parameters.lock().unwrap();
-        let search_result = match search(&mut dust_last_frame_data, &params) {
-            Ok(result) => result,
-            Err(e) => {
-                *output.lock().unwrap() = DustSearchResult {
-                    match_count: 0,
-                    single_matched_position: 0
-                };
-                perform_search.store(false, Ordering::Relaxed);
-                println!("Error occurred during search: {}", e);
-                continue;
-            }
-        };
-
-        let elapsed = now.elapsed();
-        println!("Elapsed: {:.2?}", elapsed);
-
-        // Output results
-        *output.lock().unwrap() = search_result;
+        DustSearchMode::LastFrame | DustSearchMode::LastFrameEarly => {}
+    }
 
-        // Allow new searches to be performed
-        perform_search.store(false, Ordering::Relaxed);
+    let frame_end_offset: usize = match search_mode {
+        DustSearchMode::LastFrame | DustSearchMode::LastFrameEarly => 1,
+        DustSearchMode::SecondToLastFrame | DustSearchMode::SecondToLastFrameEarly => 2
+    };
+    let initial_rng_skip_amount: u32 = 2 * (search_anim.get_frame_count() - frame_end_offset) as u32;
+
+    if frame_end_offset == 2 {
+        //println!("RNG skip amount between last two frames = {}", initial_rng_skip_amount);
+        //println!("expected second to last RNG frame offset = {}", search_anim.get_frame_rng_offset(search_anim.get_frame_count() - 2));
+        //println!("actual second to last RNG frame offset = {}", search_anim.get_frame_rng_offset(search_anim.get_frame_count() - 1) as u32 - ((initial_particles_second_last_frame_count * 2) + initial_rng_skip_amount));
+        assert!(search_anim.get_frame_rng_offset(search_anim.get_frame_count() - 1) as u32 - ((initial_particles_second_last_frame_count * 2) + initial_rng_skip_amount) ==
+                search_anim.get_frame_rng_offset(search_anim.get_frame_count() - 2) as u32);
     }
 
-    unload(&dust_last_frame_data);
-    println!("Dust last frame compute thread ended");
-}
\ No newline at end of file
+    let search_parameters = DustSearchParameters {
+        search_range: (rng_count - search_anim.get_total_rng_calls() - 10_000) as u32,
+        max_candidates,
+        last_frame_rng_offset: search_anim.get_frame_rng_offset(search_anim.get_frame_count() - 1) as u32,
+        matching_particles,
+        initial_particles,
+        last_frame_particle_count: initial_particles_last_frame_count,
+        second_last_frame_particle_count: initial_particles_second_last_frame_count,
+        initial_rng_skip_amount,
+        search_mode
+    };
+    (search_parameters, search_anim)
+}
+
+pub fn thread_func(job: SearchJobHandle<DustSearchParameters, DustSearchResult>,
+                   prng: Arc<PrecomputedRNG>, compute_backend: ConfigComputeBackend) {
+    eprintln!("Dust last frame compute thread started");
+    let mut backend = match compute_backend::preload_backend(compute_backend, prng, preload, preload_cpu) {
+        Ok(backend) => backend,
+        Err(e) => {
+            job.fail(e);
+            return;
+        }
+    };
+    job.run_search_loop(&mut backend);
+    eprintln!("Dust last frame compute thread ended");
+}
@@ -1,8 +1,9 @@
-use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread, time::Instant};
+use std::sync::{Arc, Mutex};
 
+use rayon::prelude::*;
 use sdl3::sys::gpu::{SDL_GPUComputePipeline, SDL_GPUDevice, SDL_GPUFence};
 
-use crate::compute_shaders::{self, ComputePipelineInfo, GPUBufferInfo};
+use crate::{compute_backend::{self, BoxedSearchBackend, SearchBackend, SearchJobHandle, MAX_SEARCH_CANDIDATES}, compute_shaders::{self, ComputePipelineInfo, GPUBufferInfo}, config::ConfigComputeBackend, rng::{RNGBatch, RNG_BATCH_LANES}};
 
 struct NamingComputeData {
     gpu_device: *mut SDL_GPUDevice,
@@ -13,13 +14,26 @@ struct NamingComputeData {
     preload_fence: *mut SDL_GPUFence
 }
 
+struct NamingCpuSearch {
+    unique_seeds: Arc<Mutex<Vec<u32>>>
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct NamingSearchCandidate {
+    pub seed: u32,
+    pub position: u32
+}
+
+#[derive(Default)]
 pub struct NamingSearchResult {
     pub match_count: u32,
-    pub single_matched_seed: u32,
-    pub single_matched_position: u32
+    // Up to the requested maximum number of matches, sorted by seed (or in order of plausibility, when searching a subset of seeds)
+    pub candidates: Vec<NamingSearchCandidate>
 }
 
-fn preload(unique_seeds: &Vec<u32>) -> Result<NamingComputeData, &'static str> {
+fn preload(unique_seeds: &Arc<Mutex<Vec<u32>>>) -> Result<BoxedSearchBackend<NamingSearchParameters, NamingSearchResult>, &'static str> {
+    let unique_seeds = unique_seeds.lock().unwrap();
+
     // Create device and pipeline based on shader
     let device = compute_shaders::create_gpu_device()?;
     let pipeline = compute_shaders::create_compute_pipeline(device, &ComputePipelineInfo {
@@ -32,7 +46,7 @@ fn preload(unique_seeds: &Vec<u32>) -> Result<NamingComputeData, &'static str> {
     })?;
 
     // Create GPU buffers
-    let return_val_buffer = compute_shaders::create_gpu_buffer(device, 16, false, true)?;
+    let return_val_buffer = compute_shaders::create_gpu_buffer(device, 4 + (MAX_SEARCH_CANDIDATES as usize * 8), false, true)?;
     let rng_seeds_count = unique_seeds.len() as u32;
     let rng_seeds_buffer = compute_shaders::create_gpu_buffer(device, unique_seeds.len() * 4, true, false)?;
 
@@ -43,118 +57,210 @@ fn preload(unique_seeds: &Vec<u32>) -> Result<NamingComputeData, &'static str> {
     compute_shaders::end_copy_pass(copy_pass);
     let preload_fence = compute_shaders::end_command_buffer_and_get_fence(command_buffer)?;
 
-    Ok(NamingComputeData { 
+    Ok(Box::new(NamingComputeData { 
         gpu_device: device,
         pipeline,
         return_val_buffer,
         rng_seeds_buffer,
         rng_seeds_count,
         preload_fence
-    })
+    }))
+}
+
+fn preload_cpu(unique_seeds: Arc<Mutex<Vec<u32>>>) -> BoxedSearchBackend<NamingSearchParameters, NamingSearchResult> {
+    Box::new(NamingCpuSearch { unique_seeds })
 }
 
-fn search(data: &mut NamingComputeData, params: &NamingSearchParameters) -> Result<NamingSearchResult, &'static str> {
-    // If there's a preload fence to wait for and/or release, do so
-    if !data.preload_fence.is_null() {
-        let success = compute_shaders::wait_for_and_release_fences(data.gpu_device, &[data.preload_fence]);
-        data.preload_fence = std::ptr::null_mut() as *mut SDL_GPUFence;
+impl SearchBackend for NamingComputeData {
+    type Parameters = NamingSearchParameters;
+    type Result = NamingSearchResult;
 
-        if !success {
-            return Err("Preload command buffer was unsuccessful");
+    fn search(&mut self, params: &NamingSearchParameters) -> Result<NamingSearchResult, &'static str> {
+        // If there's a preload fence to wait for and/or release, do so
+        if !self.preload_fence.is_null() {
+            let success = compute_shaders::wait_for_and_release_fences(self.gpu_device, &[self.preload_fence]);
+            self.preload_fence = std::ptr::null_mut() as *mut SDL_GPUFence;
+
+            if !success {
+                return Err("Preload command buffer was unsuccessful");
+            }
         }
-    }
 
-    // Verify number of pixels are valid, and build match integers
-    if params.matching_pixels.len() != 104 {
-        return Err("Expected 104 matching pixels");
-    }
-    let mut match1: u32 = 0;
-    let mut match2: u32 = 0;
-    let mut match3: u32 = 0;
-    let mut match4: u32 = 0;
-    for i in 0..8 {
-        if params.matching_pixels[i] {
-            match4 |= 1 << (7 - i);
+        // Verify parameters are valid, and build match integers
+        compute_backend::validate_max_candidates(params.max_candidates)?;
+        let match_bits = build_match_bits(&params.matching_pixels)?;
+
+        // Subsets are small (and not sized for the shader's thread groups), so search them on the CPU
+        if let Some(seed_subset) = &params.seed_subset {
+            return Ok(search_seeds_parallel(seed_subset, params, match_bits));
         }
-    }
-    for i in 8..40 {
-        if params.matching_pixels[i] {
-            match3 |= 1 << (31 - (i - 8));
+        let match1: u32 = match_bits as u32;
+        let match2: u32 = (match_bits >> 32) as u32;
+        let match3: u32 = (match_bits >> 64) as u32;
+        let match4: u32 = (match_bits >> 96) as u32;
+
+        // Create command buffer for all operations
+        let command_buffer = compute_shaders::begin_command_buffer(self.gpu_device)?;
+
+        // Copy search data to GPU buffers
+        let copy_pass = compute_shaders::begin_copy_pass(command_buffer)?;
+        compute_shaders::upload_to_gpu_buffer(self.gpu_device, copy_pass, &self.return_val_buffer, &[0; 4])?;
+        compute_shaders::end_copy_pass(copy_pass);
+
+        // Push uniform data
+        let mut random_flags = 0;
+        if params.rng_15bit {
+            random_flags |= 1 << 0;
         }
-    }
-    for i in 40..72 {
-        if params.matching_pixels[i] {
-            match2 |= 1 << (31 - (i - 40));
+        if params.rng_signed {
+            random_flags |= 1 << 1;
         }
-    }
-    for i in 72..104 {
-        if params.matching_pixels[i] {
-            match1 |= 1 << (31 - (i - 72));
+        if params.rng_old_poly {
+            random_flags |= 1 << 2;
         }
-    }
+        let uniform_data = [
+            u32::to_ne_bytes(random_flags), 
+            u32::to_ne_bytes(params.search_range), 
+            u32::to_ne_bytes(match1), 
+            u32::to_ne_bytes(match2),
+            u32::to_ne_bytes(match3),
+            u32::to_ne_bytes(match4),
+            u32::to_ne_bytes(params.max_candidates)
+        ].concat();
+        compute_shaders::push_uniform_data(command_buffer, 0, &uniform_data);
 
-    // Create command buffer for all operations
-    let command_buffer = compute_shaders::begin_command_buffer(data.gpu_device)?;
+        // Run main search operation
+        let writeable_buffer_storage = [self.return_val_buffer.raw()];
+        let all_buffer_storage_ordered = [self.rng_seeds_buffer.raw()];
+        compute_shaders::perform_buffer_compute(command_buffer, self.pipeline, &writeable_buffer_storage, &all_buffer_storage_ordered, self.rng_seeds_count / 64, 1, 1)?;
 
-    // Copy search data to GPU buffers
-    let copy_pass = compute_shaders::begin_copy_pass(command_buffer)?;
-    compute_shaders::upload_to_gpu_buffer(data.gpu_device, copy_pass, &data.return_val_buffer, &[0; 12])?;
-    compute_shaders::end_copy_pass(copy_pass);
+        // Start download of data from return value buffer
+        let copy_pass = compute_shaders::begin_copy_pass(command_buffer)?;
+        let download_transfer_buffer = compute_shaders::queue_download_from_gpu_buffer(self.gpu_device, copy_pass, &self.return_val_buffer)?;
+        compute_shaders::end_copy_pass(copy_pass);
 
-    // Push uniform data
-    let mut random_flags = 0;
-    if params.rng_15bit {
-        random_flags |= 1 << 0;
+        // End command buffer and wait for it to finish
+        let success = compute_shaders::end_command_buffer_and_wait_for_fence(self.gpu_device, command_buffer);
+        if !success {
+            return Err("Search command buffer was unsuccessful");
+        }
+
+        // Finish download of data from return value buffer
+        let mut output_buffer: Vec<u8> = vec![0; 4 + (params.max_candidates as usize * 8)];
+        compute_shaders::finish_download_from_gpu_buffer(self.gpu_device, &self.return_val_buffer, download_transfer_buffer, &mut output_buffer)?;
+
+        // Interpret final data (candidates are written in no particular order, so sort them)
+        let match_count = u32::from_ne_bytes(output_buffer[0..4].try_into().unwrap());
+        let mut candidates: Vec<NamingSearchCandidate> = output_buffer[4..].chunks_exact(8)
+            .take(u32::min(match_count, params.max_candidates) as usize)
+            .map(|candidate| NamingSearchCandidate {
+                seed: u32::from_ne_bytes(candidate[0..4].try_into().unwrap()),
+                position: u32::from_ne_bytes(candidate[4..8].try_into().unwrap())
+            })
+            .collect();
+        candidates.sort_by_key(|candidate| candidate.seed);
+        Ok(NamingSearchResult {
+            match_count,
+            candidates
+        })
     }
-    if params.rng_signed {
-        random_flags |= 1 << 1;
+
+    fn unload(&mut self) {
+        compute_shaders::free_gpu_buffer(self.gpu_device, &self.rng_seeds_buffer);
+        compute_shaders::free_gpu_buffer(self.gpu_device, &self.return_val_buffer);
+        compute_shaders::free_compute_pipeline(self.gpu_device, self.pipeline);
+        compute_shaders::free_gpu_device(self.gpu_device);
     }
-    if params.rng_old_poly {
-        random_flags |= 1 << 2;
+}
+
+// Pixel thresholds for a random(0.5) being > 0.25 ("actual"), and definitely visible as such ("guaranteed")
+const ACTUAL_THRESHOLD: u32 = 2147483647;
+const GUARANTEED_THRESHOLD: u32 = 2164663517;
+
+// Mask for the 104 bits used by the sliding windows
+const WINDOW_MASK: u128 = (1 << 104) - 1;
+
+// Verifies number of pixels are valid, and packs them into a single 104-bit integer (first pixel is the highest bit)
+fn build_match_bits(matching_pixels: &[bool]) -> Result<u128, &'static str> {
+    if matching_pixels.len() != 104 {
+        return Err("Expected 104 matching pixels");
     }
-    let uniform_data = [
-        u32::to_ne_bytes(random_flags), 
-        u32::to_ne_bytes(params.search_range), 
-        u32::to_ne_bytes(match1), 
-        u32::to_ne_bytes(match2),
-        u32::to_ne_bytes(match3),
-        u32::to_ne_bytes(match4)
-    ].concat();
-    compute_shaders::push_uniform_data(command_buffer, 0, &uniform_data);
-
-    // Run main search operation
-    let writeable_buffer_storage = [data.return_val_buffer.raw()];
-    let all_buffer_storage_ordered = [data.rng_seeds_buffer.raw()];
-    compute_shaders::perform_buffer_compute(command_buffer, data.pipeline, &writeable_buffer_storage, &all_buffer_storage_ordered, data.rng_seeds_count / 64, 1, 1)?;
-
-    // Start download of data from return value buffer
-    let copy_pass = compute_shaders::begin_copy_pass(command_buffer)?;
-    let download_transfer_buffer = compute_shaders::queue_download_from_gpu_buffer(data.gpu_device, copy_pass, &data.return_val_buffer)?;
-    compute_shaders::end_copy_pass(copy_pass);
+    let mut match_bits: u128 = 0;
+    for (i, selected) in matching_pixels.iter().enumerate() {
+        if *selected {
+            match_bits |= 1 << (103 - i);
+        }
+    }
+    Ok(match_bits)
+}
+
+// Searches a batch of seeds on the CPU in lock-step, each equivalent to a single invocation of the naming shader.
+// Returns the position just after the matched pattern for each seed, if any.
+fn search_seeds_cpu(seeds: &[u32], params: &NamingSearchParameters, match_bits: u128) -> Vec<Option<u32>> {
+    let mut batch = RNGBatch::new(seeds, params.rng_15bit, params.rng_signed, params.rng_old_poly);
 
-    // End command buffer and wait for it to finish
-    let success = compute_shaders::end_command_buffer_and_wait_for_fence(data.gpu_device, command_buffer);
-    if !success {
-        return Err("Search command buffer was unsuccessful");
+    // Keep a buffer of the last 104 pixels for each seed, for both "actual" and "guaranteed"
+    let mut actual: [u128; RNG_BATCH_LANES] = [0; RNG_BATCH_LANES];
+    let mut guaranteed: [u128; RNG_BATCH_LANES] = [0; RNG_BATCH_LANES];
+    let mut positions: Vec<Option<u32>> = vec![None; seeds.len()];
+    let mut remaining = seeds.len();
+    let mut i = 0;
+    while i < params.search_range && remaining > 0 {
+        // Vertical position, then horizontal position
+        let values_y = batch.next_u32();
+        let values_x = batch.next_u32();
+        for (lane, position) in positions.iter_mut().enumerate() {
+            let (value_y, value_x) = (values_y[lane], values_x[lane]);
+            let actual_bits = (((value_y >= ACTUAL_THRESHOLD) as u128) << 1) | ((value_x >= ACTUAL_THRESHOLD) as u128);
+            let guaranteed_bits = (((value_y >= GUARANTEED_THRESHOLD) as u128) << 1) | ((value_x >= GUARANTEED_THRESHOLD) as u128);
+            actual[lane] = ((actual[lane] << 2) | actual_bits) & WINDOW_MASK;
+            guaranteed[lane] = ((guaranteed[lane] << 2) | guaranteed_bits) & WINDOW_MASK;
+
+            // All 1s in the match buffer *must* be present in the actual buffer.
+            // All 1s in the guaranteed buffer *must* be present in the match buffer.
+            if position.is_none() && (actual[lane] & match_bits) == match_bits && (guaranteed[lane] & match_bits) == guaranteed[lane] {
+                *position = Some(i + 2);
+                remaining -= 1;
+            }
+        }
+
+        i += 2;
     }
+    positions
+}
 
-    // Finish download of data from return value buffer
-    let mut output_buffer: Vec<u8> = vec![0; 12];
-    compute_shaders::finish_download_from_gpu_buffer(data.gpu_device, &data.return_val_buffer, download_transfer_buffer, &mut output_buffer)?;
+// Searches all given seeds in parallel on the CPU, a batch at a time, keeping the order of the seeds
+fn search_seeds_parallel(seeds: &[u32], params: &NamingSearchParameters, match_bits: u128) -> NamingSearchResult {
+    let mut candidates: Vec<NamingSearchCandidate> = seeds.par_chunks(RNG_BATCH_LANES)
+        .flat_map_iter(|seeds| {
+            let positions = search_seeds_cpu(seeds, params, match_bits);
+            seeds.iter().zip(positions).filter_map(|(&seed, position)| position.map(|position| NamingSearchCandidate { seed, position }))
+        })
+        .collect();
 
-    // Interpret final data
-    Ok(NamingSearchResult {
-        match_count: u32::from_ne_bytes(output_buffer[0..4].try_into().unwrap()),
-        single_matched_seed: u32::from_ne_bytes(output_buffer[4..8].try_into().unwrap()),
-        single_matched_position: u32::from_ne_bytes(output_buffer[8..12].try_into().unwrap())
-    })
+    let match_count = candidates.len() as u32;
+    candidates.truncate(params.max_candidates as usize);
+    NamingSearchResult {
+        match_count,
+        candidates
+    }
 }
 
-fn unload(data: &NamingComputeData) {
-    compute_shaders::free_gpu_buffer(data.gpu_device, &data.rng_seeds_buffer);
-    compute_shaders::free_gpu_buffer(data.gpu_device, &data.return_val_buffer);
-    compute_shaders::free_compute_pipeline(data.gpu_device, data.pipeline);
-    compute_shaders::free_gpu_device(data.gpu_device);
+impl SearchBackend for NamingCpuSearch {
+    type Parameters = NamingSearchParameters;
+    type Result = NamingSearchResult;
+
+    fn search(&mut self, params: &NamingSearchParameters) -> Result<NamingSearchResult, &'static str> {
+        compute_backend::validate_max_candidates(params.max_candidates)?;
+        let match_bits = build_match_bits(&params.matching_pixels)?;
+
+        if let Some(seed_subset) = &params.seed_subset {
+            return Ok(search_seeds_parallel(seed_subset, params, match_bits));
+        }
+
+        // Unique seeds are already sorted
+        Ok(search_seeds_parallel(&self.unique_seeds.lock().unwrap(), params, match_bits))
+    }
 }
 
 pub struct NamingSearchParameters {
@@ -162,66 +268,22 @@ pub struct NamingSearchParameters {
     pub rng_signed: bool,
     pub rng_old_poly: bool,
     pub search_range: u32,
-    pub matching_pixels: Vec<bool>
+    pub max_candidates: u32,
+    pub matching_pixels: Vec<bool>,
+    // If set, only these seeds get searched (in order of plausibility), rather than all unique seeds
+    pub seed_subset: Option<Arc<Vec<u32>>>
 }
 
-pub fn thread_func(end_thread: Arc<AtomicBool>, perform_search: Arc<AtomicBool>,
-                   unique_seeds: Arc<Mutex<Vec<u32>>>, parameters: Arc<Mutex<NamingSearchParameters>>,
-                   output: Arc<Mutex<NamingSearchResult>>) {
-    println!("Naming compute thread started");
-    let mut naming_data = preload(&unique_seeds.lock().unwrap()).expect("Failed to preload");
-
-    loop {
-        // Wait until an end thread or perform search signal are sent
-        let mut end_thread_signal = false;
-        let mut perform_search_signal = false;
-        loop {
-            if end_thread.load(Ordering::Relaxed) {
-                end_thread.store(false, Ordering::Relaxed);
-                end_thread_signal = true;
-                break;
-            }
-            if perform_search.load(Ordering::Relaxed) {
-                perform_search_signal = true;
-                break;
-            }
-            thread::park();
-        }
-        if end_thread_signal {
-            break;
-        }
-        if !perform_search_signal {
-            continue;
+pub fn thread_func(job: SearchJobHandle<NamingSearchParameters, NamingSearchResult>,
+                   unique_seeds: Arc<Mutex<Vec<u32>>>, compute_backend: ConfigComputeBackend) {
+    eprintln!("Naming compute thread started");
+    let mut backend = match compute_backend::preload_backend(compute_backend, unique_seeds, preload, preload_cpu) {
+        Ok(backend) => backend,
+        Err(e) => {
+            job.fail(e);
+            return;
         }
-        
-        let now = Instant::now();
-
-        // Begin search with current parameters
-        let params = parameters.lock().unwrap();
-        let search_result = match search(&mut naming_data, &params) {
-            Ok(result) => result,
-            Err(e) => {
-                *output.lock().unwrap() = NamingSearchResult {
-                    match_count: 0,
-                    single_matched_seed: 0,
-                    single_matched_position: 0
-                };
-                perform_search.store(false, Ordering::Relaxed);
-                println!("Error occurred during search: {}", e);
-                continue;
-            }
-        };
-
-        let elapsed = now.elapsed();
-        println!("Elapsed: {:.2?}", elapsed);
-
-        // Output results
-        *output.lock().unwrap() = search_result;
-
-        // Allow new searches to be performed
-        perform_search.store(false, Ordering::Relaxed);
-    }
-
-    unload(&naming_data);
-    println!("Naming compute thread ended");
-}
\ No newline at end of file
+    };
+    job.run_search_loop(&mut backend);
+    eprintln!("Naming compute thread ended");
+}
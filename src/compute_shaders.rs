@@ -342,7 +342,7 @@ pub fn finish_download_from_gpu_buffer(device: *mut SDL_GPUDevice, buffer_info:
     }
 
     // Copy data to output buffer
-    unsafe { ptr::copy_nonoverlapping(transfer_buffer_data as *const u8, &mut output_buffer[0] as *mut u8, output_buffer.len()) };
+    unsafe { ptr::copy_nonoverlapping(transfer_buffer_data as *const u8, output_buffer.as_mut_ptr(), output_buffer.len()) };
 
     // Unmap transfer buffer memory from application address space
     unsafe { SDL_UnmapGPUTransferBuffer(device, transfer_buffer) };
@@ -400,7 +400,7 @@ pub fn perform_buffer_compute(command_buffer: *mut SDL_GPUCommandBuffer, compute
     Ok(())
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct PointU32 {
     value: u32
 }
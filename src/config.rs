@@ -1,20 +1,43 @@
-use std::{fs, io::Error};
+use std::{collections::BTreeMap, fmt, fs, time::{Duration, Instant, SystemTime}};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-use crate::program_common::SubProgram;
+use crate::{compute_backend::MAX_SEARCH_CANDIDATES, compute_snowball_search::MAX_SEARCH_RANGE as MAX_SNOWBALL_SEARCH_RANGE, program_common::{SubProgram, DEFAULT_SCREEN_HEIGHT, DEFAULT_SCREEN_WIDTH}};
+
+// How often to check config.json for changes, while the tool is running
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Current config file layout. When changing the layout in a way that older files need adjusting for, increase this and add a migration step.
+const CONFIG_VERSION: u32 = 1;
+
+// Steps to upgrade each older layout to the next (index 0 upgrades version 0 to 1, and so on)
+const CONFIG_MIGRATIONS: [fn(&mut Map<String, Value>); CONFIG_VERSION as usize] = [
+    migrate_v0_to_v1
+];
+
+// Configs from before versioning. Automatically advancing from the naming seed search didn't exist yet, so it stays off for these.
+fn migrate_v0_to_v1(config: &mut Map<String, Value>) {
+    config.entry("naming_auto_advance_seconds").or_insert(Value::from(0));
+}
 
 // Based on GameMaker runner version (so Linux v1.001 on Windows falls under Undertale_Windows_v1_001)
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Deserialize, Serialize, Clone, Copy)]
 #[expect(non_camel_case_types)]
 pub enum ConfigRunnerVersion {
     Undertale_Windows_v1_0,
     Undertale_Windows_v1_001,
     Undertale_Linux_v1_001,
-    Undertale_Windows_v1_08,
-    // TODO: add more supported configurations?
+    Undertale_Windows_v1_08
 }
 impl ConfigRunnerVersion {
+    const ALL: [ConfigRunnerVersion; 4] = [
+        Self::Undertale_Windows_v1_0,
+        Self::Undertale_Windows_v1_001,
+        Self::Undertale_Linux_v1_001,
+        Self::Undertale_Windows_v1_08
+    ];
+
     pub fn rng_15bit(&self) -> bool {
         match self {
             Self::Undertale_Windows_v1_0 => true,
@@ -49,7 +72,106 @@ impl ConfigRunnerVersion {
     }
 }
 
-#[derive(Deserialize, Clone)]
+// Custom runner profile, for builds/ports not covered by a preset. Any properties left out are taken from the preset it's based on.
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigCustomRunnerProfile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    based_on: Option<ConfigRunnerVersion>,
+    rng_15bit: Option<bool>,
+    rng_signed: Option<bool>,
+    rng_old_poly: Option<bool>,
+    circle_draw_offset: Option<i32>
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum ConfigRunnerSetting {
+    Preset(ConfigRunnerVersion),
+    Custom(ConfigCustomRunnerProfile)
+}
+
+// Runner properties in use, either from a preset runner version, or a custom profile
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(try_from = "ConfigRunnerSetting", into = "ConfigRunnerSetting")]
+pub struct ConfigRunnerProfile {
+    rng_15bit: bool,
+    rng_signed: bool,
+    rng_old_poly: bool,
+    circle_draw_offset: i32
+}
+impl ConfigRunnerProfile {
+    pub fn rng_15bit(&self) -> bool {
+        self.rng_15bit
+    }
+    pub fn rng_signed(&self) -> bool {
+        self.rng_signed
+    }
+    pub fn rng_old_poly(&self) -> bool {
+        self.rng_old_poly
+    }
+    pub fn circle_draw_offset(&self) -> i32 {
+        self.circle_draw_offset
+    }
+}
+impl From<ConfigRunnerVersion> for ConfigRunnerProfile {
+    fn from(version: ConfigRunnerVersion) -> Self {
+        ConfigRunnerProfile {
+            rng_15bit: version.rng_15bit(),
+            rng_signed: version.rng_signed(),
+            rng_old_poly: version.rng_old_poly(),
+            circle_draw_offset: version.circle_draw_offset()
+        }
+    }
+}
+impl From<ConfigRunnerProfile> for ConfigRunnerSetting {
+    // Written back by preset name when possible, otherwise as a full custom profile
+    fn from(profile: ConfigRunnerProfile) -> Self {
+        if let Some(version) = ConfigRunnerVersion::ALL.into_iter().find(|&version| ConfigRunnerProfile::from(version) == profile) {
+            return ConfigRunnerSetting::Preset(version);
+        }
+        ConfigRunnerSetting::Custom(ConfigCustomRunnerProfile {
+            based_on: None,
+            rng_15bit: Some(profile.rng_15bit),
+            rng_signed: Some(profile.rng_signed),
+            rng_old_poly: Some(profile.rng_old_poly),
+            circle_draw_offset: Some(profile.circle_draw_offset)
+        })
+    }
+}
+impl TryFrom<ConfigRunnerSetting> for ConfigRunnerProfile {
+    type Error = &'static str;
+
+    fn try_from(setting: ConfigRunnerSetting) -> Result<Self, Self::Error> {
+        match setting {
+            ConfigRunnerSetting::Preset(version) => Ok(version.into()),
+            ConfigRunnerSetting::Custom(custom) => {
+                let base = custom.based_on.map(ConfigRunnerProfile::from);
+                Ok(ConfigRunnerProfile {
+                    rng_15bit: custom.rng_15bit.or(base.map(|base| base.rng_15bit))
+                        .ok_or("Custom runner profile needs rng_15bit (or a based_on preset)")?,
+                    rng_signed: custom.rng_signed.or(base.map(|base| base.rng_signed))
+                        .ok_or("Custom runner profile needs rng_signed (or a based_on preset)")?,
+                    rng_old_poly: custom.rng_old_poly.or(base.map(|base| base.rng_old_poly))
+                        .ok_or("Custom runner profile needs rng_old_poly (or a based_on preset)")?,
+                    circle_draw_offset: custom.circle_draw_offset.or(base.map(|base| base.circle_draw_offset))
+                        .ok_or("Custom runner profile needs circle_draw_offset (or a based_on preset)")?
+                })
+            }
+        }
+    }
+}
+
+// Which hardware to run searches on (automatic tries the GPU first, and falls back to the CPU)
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigComputeBackend {
+    Auto,
+    Gpu,
+    Cpu
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct ConfigNamingRect {
     pub x: u32,
     pub y: u32,
@@ -57,16 +179,35 @@ pub struct ConfigNamingRect {
     pub h: u32
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct ConfigNamingRects {
     pub zoom: ConfigNamingRect,
     pub crop: ConfigNamingRect
 }
 
-#[derive(Deserialize, Clone)]
+// Settings that only make sense at the top level of the file, and not within a profile
+const TOP_LEVEL_ONLY_SETTINGS: [&str; 3] = ["config_version", "default_profile", "profiles"];
+
+// Any settings missing from the file are taken from the defaults below
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
 pub struct Config {
-    pub runner_version: ConfigRunnerVersion,
+    pub config_version: u32,
+
+    // Named profiles, each overriding any of the other settings, and which one to use when none is chosen
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Map<String, Value>>,
+
+    // Profile that was applied when loading, if any
+    #[serde(skip)]
+    pub active_profile: Option<String>,
+
+    pub runner_version: ConfigRunnerProfile,
     pub server_port: u16,
+    pub compute_backend: ConfigComputeBackend,
+    pub search_max_candidates: u32,
     pub hotkey_1_name: String,
     pub hotkey_2_name: String,
     pub hotkey_3_name: String,
@@ -82,13 +223,399 @@ pub struct Config {
     pub snowball_search_range: u32,
     pub naming_advance_tool: SubProgram,
     pub naming_auto_advance_seconds: u32,
+    pub seed_time_tolerance_ms: u32,
+    pub seed_time_offset_ms: u32,
     pub naming_rects: Vec<ConfigNamingRects>
 }
+impl Default for Config {
+    fn default() -> Self {
+        let naming_rects = |zoom_y: u32, crop_y: u32| ConfigNamingRects {
+            zoom: ConfigNamingRect { x: 100, y: zoom_y, w: 440, h: 330 },
+            crop: ConfigNamingRect { x: 100, y: crop_y, w: 440, h: 130 }
+        };
+        Config {
+            config_version: CONFIG_VERSION,
+            default_profile: None,
+            profiles: BTreeMap::new(),
+            active_profile: None,
+            runner_version: if cfg!(target_os = "linux") { ConfigRunnerVersion::Undertale_Linux_v1_001 } else { ConfigRunnerVersion::Undertale_Windows_v1_001 }.into(),
+            server_port: 48654,
+            compute_backend: ConfigComputeBackend::Auto,
+            search_max_candidates: 8,
+            hotkey_1_name: "F1".to_string(),
+            hotkey_2_name: "F2".to_string(),
+            hotkey_3_name: "F3".to_string(),
+            hotkey_4_name: "F5".to_string(),
+            mouse_warps: true,
+            window_always_on_top: false,
+            window_unfocusable_by_default: false,
+            window_opacity: 1.0,
+            reset_return_to: SubProgram::NamingSeedSearch,
+            auto_return_to_naming_search: false,
+            snowball_immediate_place: false,
+            snowball_search_start_offset: 0,
+            snowball_search_range: 500000,
+            naming_advance_tool: SubProgram::DogiManip,
+            naming_auto_advance_seconds: 3,
+            seed_time_tolerance_ms: 5000,
+            seed_time_offset_ms: 0,
+            naming_rects: vec![naming_rects(42, 142), naming_rects(170, 270)]
+        }
+    }
+}
+// Problem found while loading the config, along with where it is and how to fix it
+pub struct ConfigProblem {
+    pub field: String,
+    pub message: String,
+    pub suggestion: String
+}
+impl ConfigProblem {
+    fn new(field: impl Into<String>, message: impl Into<String>, suggestion: impl Into<String>) -> Self {
+        ConfigProblem {
+            field: field.into(),
+            message: message.into(),
+            suggestion: suggestion.into()
+        }
+    }
+}
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} {}", self.field, self.message, self.suggestion)
+    }
+}
+
 impl Config {
-    pub fn read() -> Result<Self, Error> {
-        let contents = fs::read_to_string("config.json")?;
-        let config: Self = serde_json::from_str(&contents)?;
+    // Reads config.json, applying the given profile (or the default profile, if none is given)
+    pub fn read(profile: Option<&str>) -> Result<Self, Vec<ConfigProblem>> {
+        let (config, outdated) = Self::parse(&Self::read_file()?, profile)?;
 
+        let problems = config.validate();
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        if outdated {
+            eprintln!("config.json is from an older version of the tool, so defaults were used for any missing settings. Run with --upgrade-config to update the file.");
+        }
         Ok(config)
     }
+
+    // Rewrites config.json in the current layout, with every setting included (and profiles left as they are)
+    pub fn upgrade_file() -> Result<(), String> {
+        let problems_to_string = |problems: Vec<ConfigProblem>| problems.iter().map(|problem| problem.to_string()).collect::<Vec<String>>().join("\n");
+        let (settings, _) = Self::parse_settings(&Self::read_file().map_err(problems_to_string)?).map_err(problems_to_string)?;
+        let config = Self::from_settings(settings).map_err(problems_to_string)?;
+
+        let mut contents: Vec<u8> = Vec::new();
+        let mut serializer = serde_json::Serializer::with_formatter(&mut contents, serde_json::ser::PrettyFormatter::with_indent(b"    "));
+        config.serialize(&mut serializer).map_err(|e| e.to_string())?;
+        fs::write("config.json", contents).map_err(|e| e.to_string())
+    }
+
+    fn read_file() -> Result<String, Vec<ConfigProblem>> {
+        fs::read_to_string("config.json").map_err(|e| vec![ConfigProblem::new("config.json",
+            format!("Couldn't be read ({}).", e),
+            "Make sure config.json is in the same folder the tool is run from.")])
+    }
+
+    // Parses and upgrades the config from the given file contents, with a profile applied (as in read), also returning whether the file itself is outdated
+    fn parse(contents: &str, profile: Option<&str>) -> Result<(Self, bool), Vec<ConfigProblem>> {
+        let (mut settings, outdated) = Self::parse_settings(contents)?;
+        let profile = profile.map(str::to_string)
+            .or_else(|| settings.get("default_profile").and_then(Value::as_str).map(str::to_string));
+        if let Some(profile) = &profile {
+            Self::apply_profile(&mut settings, profile)?;
+        }
+        let mut config = Self::from_settings(settings)?;
+        config.active_profile = profile;
+        Ok((config, outdated))
+    }
+
+    // Parses the settings in the file and upgrades them to the current layout, also returning whether the file itself is outdated
+    fn parse_settings(contents: &str) -> Result<(Map<String, Value>, bool), Vec<ConfigProblem>> {
+        let value: Value = serde_json::from_str(contents).map_err(|e| vec![ConfigProblem::new(format!("config.json (line {}, column {})", e.line(), e.column()),
+            format!("Isn't valid JSON ({}).", e),
+            "Check for missing or extra commas, quotes, and brackets around this spot.")])?;
+        let Value::Object(mut settings) = value else {
+            return Err(vec![ConfigProblem::new("config.json", "Doesn't contain a JSON object.",
+                "Make sure all settings are inside one pair of curly brackets.")]);
+        };
+
+        // Upgrade from older layouts
+        let version = match settings.get("config_version") {
+            None => 0,
+            Some(version) => match version.as_u64() {
+                Some(version) if version <= CONFIG_VERSION as u64 => version as u32,
+                _ => return Err(vec![ConfigProblem::new("config_version", format!("Is {}, which this version of the tool doesn't support (the latest it knows is {}).", version, CONFIG_VERSION),
+                    "Update the tool, or use the config.json that comes with this version.")])
+            }
+        };
+        for migration in &CONFIG_MIGRATIONS[version as usize..] {
+            migration(&mut settings);
+        }
+        settings.insert("config_version".to_string(), Value::from(CONFIG_VERSION));
+
+        // Check settings one at a time, to report every unknown or invalid one by name
+        let setting_names = Self::setting_names();
+        let mut problems: Vec<ConfigProblem> = Vec::new();
+        for (key, value) in &settings {
+            if !setting_names.contains(key) && !TOP_LEVEL_ONLY_SETTINGS.contains(&key.as_str()) {
+                problems.push(ConfigProblem::new(key.as_str(), "Isn't a known setting.", "Check the spelling against the README."));
+            } else if let Some(e) = Self::check_setting(key, value) {
+                problems.push(ConfigProblem::new(key.as_str(), format!("Has an invalid value ({}).", e),
+                    "Compare this setting against the README, or the config.json that comes with the tool."));
+            }
+        }
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        // Anything missing from the file (after upgrading) means it's outdated
+        let outdated = version < CONFIG_VERSION || setting_names.iter().any(|key| !settings.contains_key(key));
+        Ok((settings, outdated))
+    }
+
+    // Names of all settings written to a default config file
+    fn setting_names() -> Vec<String> {
+        let Ok(Value::Object(all_settings)) = serde_json::to_value(Self::default()) else { unreachable!() };
+        all_settings.into_iter().map(|(key, _)| key).collect()
+    }
+
+    // Checks whether a single setting is valid on its own (with the rest defaulted), returning the error if not
+    fn check_setting(key: &str, value: &Value) -> Option<serde_json::Error> {
+        let single_setting = Value::Object(Map::from_iter([(key.to_string(), value.clone())]));
+        serde_json::from_value::<Self>(single_setting).err()
+    }
+
+    // Overrides settings with those from the given profile
+    fn apply_profile(settings: &mut Map<String, Value>, profile: &str) -> Result<(), Vec<ConfigProblem>> {
+        let Some(profile_settings) = settings.get("profiles").and_then(|profiles| profiles.get(profile)).and_then(Value::as_object).cloned() else {
+            let profile_names: Vec<&str> = settings.get("profiles").and_then(Value::as_object)
+                .map(|profiles| profiles.keys().map(String::as_str).collect())
+                .unwrap_or_default();
+            let suggestion = if profile_names.is_empty() {
+                "Add it under profiles, as described in the README.".to_string()
+            } else {
+                format!("Use one of: {}.", profile_names.join(", "))
+            };
+            return Err(vec![ConfigProblem::new("profiles", format!("Has no profile named \"{}\".", profile), suggestion)]);
+        };
+
+        let setting_names = Self::setting_names();
+        let mut problems: Vec<ConfigProblem> = Vec::new();
+        for (key, value) in profile_settings {
+            let field = format!("profiles.{}.{}", profile, key);
+            if TOP_LEVEL_ONLY_SETTINGS.contains(&key.as_str()) {
+                problems.push(ConfigProblem::new(field, "Can't be set within a profile.", "Move it to the top level of config.json."));
+            } else if !setting_names.contains(&key) {
+                problems.push(ConfigProblem::new(field, "Isn't a known setting.", "Check the spelling against the README."));
+            } else if let Some(e) = Self::check_setting(&key, &value) {
+                problems.push(ConfigProblem::new(field, format!("Has an invalid value ({}).", e),
+                    "Compare this setting against the README, or the config.json that comes with the tool."));
+            } else {
+                settings.insert(key, value);
+            }
+        }
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        Ok(())
+    }
+
+    fn from_settings(settings: Map<String, Value>) -> Result<Self, Vec<ConfigProblem>> {
+        serde_json::from_value(Value::Object(settings)).map_err(|e| vec![ConfigProblem::new("config.json",
+            format!("Has an invalid setting ({}).", e),
+            "Compare the settings against the README, or the config.json that comes with the tool.")])
+    }
+
+    // Names of the profiles that can be switched to
+    pub fn profile_names(&self) -> Vec<&str> {
+        self.profiles.keys().map(String::as_str).collect()
+    }
+
+    // Checks for values that would only fail (or misbehave) later on, collecting every problem found
+    fn validate(&self) -> Vec<ConfigProblem> {
+        let mut problems: Vec<ConfigProblem> = Vec::new();
+
+        if self.server_port == 0 {
+            problems.push(ConfigProblem::new("server_port", "Port 0 can't be listened on.",
+                "Use the default of 48654, or any other free port (matching the OBS plugin's filter settings)."));
+        }
+        if self.search_max_candidates == 0 || self.search_max_candidates > MAX_SEARCH_CANDIDATES {
+            problems.push(ConfigProblem::new("search_max_candidates", format!("Is {}, but must be between 1 and {}.", self.search_max_candidates, MAX_SEARCH_CANDIDATES),
+                "Use the default of 8."));
+        }
+        if !(self.window_opacity > 0.0 && self.window_opacity <= 1.0) {
+            problems.push(ConfigProblem::new("window_opacity", format!("Is {}, but must be above 0 and at most 1.", self.window_opacity),
+                "Use 1 for a fully opaque window, or something like 0.75 for a partially transparent one."));
+        }
+        if matches!(self.reset_return_to, SubProgram::None | SubProgram::Error) {
+            problems.push(ConfigProblem::new("reset_return_to", "Isn't a tool that can be returned to.",
+                "Use NamingSeedSearch, DogiManip, or DustManip."));
+        }
+        if matches!(self.naming_advance_tool, SubProgram::None | SubProgram::Error) {
+            problems.push(ConfigProblem::new("naming_advance_tool", "Isn't a tool that can be advanced to.",
+                "Use DogiManip, ProgramSelector, or DustManip."));
+        }
+        if self.snowball_search_range == 0 {
+            problems.push(ConfigProblem::new("snowball_search_range", "Is 0, so no snowball patterns would be searched.",
+                "Use the default of 500000."));
+        } else if self.snowball_search_range > MAX_SNOWBALL_SEARCH_RANGE {
+            problems.push(ConfigProblem::new("snowball_search_range", format!("Is {}, which would use too much memory (the maximum is {}).", self.snowball_search_range, MAX_SNOWBALL_SEARCH_RANGE),
+                "Use the default of 500000, which is generous for a regular speedrun."));
+        }
+        if self.naming_rects.is_empty() {
+            problems.push(ConfigProblem::new("naming_rects", "Is empty, but the naming seed search needs at least one pair of rectangles.",
+                "Copy the naming_rects from the config.json that comes with the tool."));
+        }
+        for (i, naming_rects) in self.naming_rects.iter().enumerate() {
+            for (name, rect) in [("zoom", &naming_rects.zoom), ("crop", &naming_rects.crop)] {
+                if rect.w == 0 || rect.h == 0 {
+                    problems.push(ConfigProblem::new(format!("naming_rects[{}].{}", i, name), format!("Has a size of {}x{}, so nothing would be visible.", rect.w, rect.h),
+                        "Give the rectangle a nonzero width and height."));
+                }
+            }
+            let crop = &naming_rects.crop;
+            if crop.x as u64 + crop.w as u64 > DEFAULT_SCREEN_WIDTH as u64 || crop.y as u64 + crop.h as u64 > DEFAULT_SCREEN_HEIGHT as u64 {
+                problems.push(ConfigProblem::new(format!("naming_rects[{}].crop", i), "Extends past the edges of the screenshot.",
+                    format!("Keep the rectangle within {}x{}.", DEFAULT_SCREEN_WIDTH, DEFAULT_SCREEN_HEIGHT)));
+            }
+        }
+
+        problems
+    }
+}
+
+// Watches config.json for changes while the tool is running, by polling its modification time
+pub struct ConfigWatcher {
+    last_modified: Option<SystemTime>,
+    last_poll: Instant
+}
+impl ConfigWatcher {
+    pub fn new() -> Self {
+        ConfigWatcher {
+            last_modified: Self::modified_time(),
+            last_poll: Instant::now()
+        }
+    }
+    fn modified_time() -> Option<SystemTime> {
+        fs::metadata("config.json").and_then(|metadata| metadata.modified()).ok()
+    }
+
+    // Returns the reloaded config (or its problems), if the file changed since it was last loaded
+    pub fn poll(&mut self, profile: Option<&str>) -> Option<Result<Config, Vec<ConfigProblem>>> {
+        if self.last_poll.elapsed() < CONFIG_POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = Self::modified_time();
+        if modified == self.last_modified {
+            return None;
+        }
+        self.last_modified = modified;
+        Some(Config::read(profile))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_configs_are_valid() {
+        for contents in [include_str!("../config.json"), include_str!("../config-for-linux.json")] {
+            let (config, outdated) = Config::parse(contents, None).ok().unwrap();
+            assert!(!outdated);
+            assert!(config.validate().is_empty());
+        }
+
+        // Built-in defaults match the file shipped for this platform
+        let (shipped, _) = Config::parse(if cfg!(target_os = "linux") { include_str!("../config-for-linux.json") } else { include_str!("../config.json") }, None).ok().unwrap();
+        assert_eq!(serde_json::to_string(&shipped).unwrap(), serde_json::to_string(&Config::default()).unwrap());
+    }
+
+    #[test]
+    fn collects_every_problem() {
+        let (mut config, _) = Config::parse(include_str!("../config.json"), None).ok().unwrap();
+        config.window_opacity = 3.0;
+        config.search_max_candidates = 0;
+        config.snowball_search_range = u32::MAX;
+        config.naming_rects[1].crop.w = 0;
+        let fields: Vec<String> = config.validate().into_iter().map(|problem| problem.field).collect();
+        assert_eq!(fields, ["search_max_candidates", "window_opacity", "snowball_search_range", "naming_rects[1].crop"]);
+
+        config.naming_rects.clear();
+        assert!(config.validate().iter().any(|problem| problem.field == "naming_rects"));
+
+        // Ranges that would need gigabytes of snowball data
+        config.snowball_search_range = 50_000_000;
+        assert!(config.validate().iter().any(|problem| problem.field == "snowball_search_range"));
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        let Err(problems) = Config::parse("{\n    \"server_port\": 48654,\n}", None) else { panic!() };
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].field.starts_with("config.json (line 3"));
+
+        let Err(problems) = Config::parse("{ \"server_port\": \"abc\", \"window_opacity\": 0.5, \"mouse_warps\": 1 }", None) else { panic!() };
+        let fields: Vec<String> = problems.into_iter().map(|problem| problem.field).collect();
+        assert_eq!(fields, ["mouse_warps", "server_port"]);
+
+        // Misspelled settings would otherwise be silently left at their defaults
+        let Err(problems) = Config::parse("{ \"sever_port\": 1234, \"window_opacity\": 0.5 }", None) else { panic!() };
+        assert_eq!((problems.len(), problems[0].field.as_str(), problems[0].message.as_str()), (1, "sever_port", "Isn't a known setting."));
+    }
+
+    #[test]
+    fn upgrades_old_configs() {
+        // Unversioned, and missing settings added later on
+        let (config, outdated) = Config::parse("{ \"server_port\": 1234, \"runner_version\": { \"based_on\": \"Undertale_Windows_v1_08\", \"circle_draw_offset\": 2 } }", None).ok().unwrap();
+        assert!(outdated);
+        assert_eq!(config.config_version, CONFIG_VERSION);
+        assert_eq!(config.server_port, 1234);
+        assert_eq!(config.naming_auto_advance_seconds, 0);
+        assert_eq!(config.search_max_candidates, Config::default().search_max_candidates);
+
+        // Writing it back keeps every value, and is no longer outdated
+        let written = serde_json::to_string(&config).unwrap();
+        let (rewritten, outdated) = Config::parse(&written, None).ok().unwrap();
+        assert!(!outdated);
+        assert_eq!(serde_json::to_string(&rewritten).unwrap(), written);
+        assert!(rewritten.runner_version.rng_signed() && rewritten.runner_version.circle_draw_offset() == 2);
+
+        // Versions from the future aren't guessed at
+        assert!(Config::parse(&format!("{{ \"config_version\": {} }}", CONFIG_VERSION + 1), None).is_err());
+    }
+
+    #[test]
+    fn applies_profiles() {
+        let contents = r#"{
+            "server_port": 1234,
+            "snowball_search_range": 1000,
+            "default_profile": "linux",
+            "profiles": {
+                "linux": { "runner_version": "Undertale_Linux_v1_001", "snowball_search_range": 2000 },
+                "v1.08": { "runner_version": "Undertale_Windows_v1_08", "reset_return_to": "DogiManip" }
+            }
+        }"#;
+
+        // The default profile is used unless another is chosen, with anything it leaves out taken from the top level
+        let (config, _) = Config::parse(contents, None).ok().unwrap();
+        assert_eq!(config.active_profile.as_deref(), Some("linux"));
+        assert_eq!((config.server_port, config.snowball_search_range, config.runner_version.circle_draw_offset()), (1234, 2000, 0));
+        assert_eq!(config.profile_names(), ["linux", "v1.08"]);
+
+        let (config, _) = Config::parse(contents, Some("v1.08")).ok().unwrap();
+        assert_eq!((config.server_port, config.snowball_search_range), (1234, 1000));
+        assert!(config.runner_version.rng_signed() && config.reset_return_to == SubProgram::DogiManip);
+
+        let Err(problems) = Config::parse(contents, Some("missing")) else { panic!() };
+        assert_eq!(problems[0].suggestion, "Use one of: linux, v1.08.");
+
+        // Problems within profiles are reported with their full path
+        let Err(problems) = Config::parse(r#"{ "profiles": { "p": { "server_port": "abc", "profiles": {}, "sever_port": 1 } } }"#, Some("p")) else { panic!() };
+        let fields: Vec<String> = problems.into_iter().map(|problem| problem.field).collect();
+        assert_eq!(fields, ["profiles.p.profiles", "profiles.p.server_port", "profiles.p.sever_port"]);
+    }
 }
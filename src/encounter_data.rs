@@ -1,6 +1,6 @@
 use sdl3::rect::Rect;
 
-use crate::{compute_dust_search::DustSearchMode, dust::{DustData, DustSearchConfig}, frame_images::{FourPixelConfig, ImagePoint}, rng::PrecomputedRNG};
+use crate::{compute_dust_search::DustSearchMode, dust::{DustData, DustSearchConfig}, frame_images::{FourPixelConfig, ImagePoint}, rng::{LinearRNG, RNG}};
 
 const FOURPIX_21_WHIMSALOT: FourPixelConfig = FourPixelConfig {
     pixel_coord_1_1: ImagePoint { x: 238, y: 314 },
@@ -390,6 +390,18 @@ pub enum Battlegroup {
 }
 
 impl Battlegroup {
+    pub const ALL: [Battlegroup; 9] = [
+        Self::Froggit,
+        Self::Astigmatism,
+        Self::Madjick,
+        Self::KnightKnight,
+        Self::FinalFroggit_Astigmatism_Whimsalot,
+        Self::KnightKnight_Madjick,
+        Self::Whimsalot_Astigmatism,
+        Self::Whimsalot_FinalFroggit,
+        Self::FinalFroggit_Astigmatism
+    ];
+
     pub fn get_dust_config(&self) -> DustSearchConfig {
         match self {
             // TODO: incomplete data
@@ -504,10 +516,11 @@ pub enum Encounterer {
 }
 
 impl Encounterer {
-    pub fn get_battlegroup_at_pos(&self, prng: &PrecomputedRNG, position: usize) -> Battlegroup {
+    // Battlegroup chosen using the RNG value at the given position (relative to the given RNG's current state)
+    pub fn get_battlegroup_at_pos(&self, rng: &RNG, position: usize) -> Battlegroup {
         match self {
             Encounterer::Core => {
-                let rng = f64::floor(prng.get_f64(15.0, position)) as u32;
+                let rng = rng.at(position).irandom(14);
                 match rng {
                     0 => Battlegroup::Madjick,
                     1 => Battlegroup::KnightKnight,
@@ -1,7 +1,13 @@
+use std::path::Path;
+
+use image::{imageops::FilterType, ImageReader};
 use sdl3::rect::Rect;
 
 use crate::server::ScreenshotData;
 
+const SCREENSHOT_WIDTH: u32 = 640;
+const SCREENSHOT_HEIGHT: u32 = 480;
+
 pub struct ImagePoint {
     pub x: u32,
     pub y: u32
@@ -112,3 +118,25 @@ pub fn clear_unwanted_pixels_snowballs(output_image: &mut Vec<u8>, screenshot_da
         y += 1;
     }
 }
+
+// Loads a screenshot from an image file (e.g. a PNG saved from OBS), converting it to the same 640x480 RGBA layout the OBS plugin sends.
+// Larger (or smaller) images are scaled, as long as they're 4:3 like the game.
+pub fn load_screenshot_file(path: &Path) -> Result<ScreenshotData, String> {
+    let image = ImageReader::open(path).map_err(|e| e.to_string())?
+        .with_guessed_format().map_err(|e| e.to_string())?
+        .decode().map_err(|e| e.to_string())?;
+    if image.width() * SCREENSHOT_HEIGHT != image.height() * SCREENSHOT_WIDTH {
+        return Err(format!("image is {}x{}, which isn't 4:3", image.width(), image.height()));
+    }
+    let image = if image.width() != SCREENSHOT_WIDTH {
+        image.resize_exact(SCREENSHOT_WIDTH, SCREENSHOT_HEIGHT, FilterType::Nearest)
+    } else {
+        image
+    };
+    Ok(ScreenshotData {
+        width: SCREENSHOT_WIDTH,
+        height: SCREENSHOT_HEIGHT,
+        stride: SCREENSHOT_WIDTH * 4,
+        data: image.to_rgba8().into_raw()
+    })
+}
@@ -7,23 +7,30 @@
   windows_subsystem = "windows"
 )]
 
-use std::{ffi::CString, panic, ptr, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver, Sender}}, thread};
-use config::Config;
-use sdl3::{render::{Canvas, TextureCreator}, video::{Window, WindowContext}, Sdl};
+use std::{env, ffi::CString, mem, panic, path::{Path, PathBuf}, process, ptr, rc::Rc, sync::{Arc, Mutex, MutexGuard, atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver, Sender}}, thread};
+use config::{Config, ConfigProblem, ConfigWatcher};
+use sdl3::{event::Event, render::{Canvas, TextureCreator}, video::{Window, WindowContext}, EventPump, Sdl};
 use sdl3_sys::{init::SDL_IsMainThread, messagebox::{SDL_MESSAGEBOX_ERROR, SDL_ShowSimpleMessageBox}, video::SDL_Window};
 use server::{MessageToSend, ScreenshotData};
+use session::{InputEvent, RunTransition, Session, SessionEvent, SessionRecord, SessionRecorder, SessionReplay};
 use text_rendering::Font;
 
-use crate::{program_common::SubProgram, windowing::{window_set_always_on_top, window_set_focusable}};
+use crate::{compute_backend::SearchJob, program_common::{Notice, ScreenSpace, SubProgram}, windowing::apply_window_config};
 
 extern crate sdl3;
 
+mod cli;
 mod rng;
+mod rng_cache;
+mod seed_timing;
 mod server;
+mod mock_client;
+mod session;
 mod text_rendering;
 mod dust;
 mod snowballs;
 mod compute_shaders;
+mod compute_backend;
 mod compute_dust_search;
 mod compute_naming_search;
 mod compute_snowball_search;
@@ -38,6 +45,7 @@ mod program_naming_seed_search;
 mod program_dogi_manip;
 mod program_error;
 mod program_rng_override;
+mod program_profile_selector;
 mod program_common;
 mod config;
 
@@ -49,13 +57,21 @@ pub struct RunContext {
     // Found RNG seed and minimum possible current RNG position
     rng_seed: u32,
     min_rng_position: usize,
+
+    // Seed clock reading from around when the game was launched, if captured
+    launch_seed_clock: Option<u32>,
+
+    // Changes made since last taken (for session recording/replay)
+    transitions: Vec<RunTransition>
 }
 impl RunContext {
     pub fn new() -> Self {
         Self {
             rng_found: false,
             rng_seed: 0,
-            min_rng_position: 0
+            min_rng_position: 0,
+            launch_seed_clock: None,
+            transitions: Vec::new()
         }
     }
     pub fn rng_seed(&self) -> Option<u32> {
@@ -68,24 +84,40 @@ impl RunContext {
         self.rng_found = true;
         self.rng_seed = rng_seed;
         self.min_rng_position = min_rng_position;
+        self.transitions.push(RunTransition::SetRng { rng_seed, min_rng_position });
     }
     pub fn set_min_rng_position(&mut self, min_rng_position: usize) {
         self.min_rng_position = min_rng_position;
+        self.transitions.push(RunTransition::SetMinRngPosition(min_rng_position));
     }
     pub fn reset(&mut self) {
         self.rng_found = false;
         self.rng_seed = 0;
         self.min_rng_position = 0;
+        self.launch_seed_clock = None;
+        self.transitions.push(RunTransition::Reset);
+    }
+    pub fn launch_seed_clock(&self) -> Option<u32> {
+        self.launch_seed_clock
+    }
+    pub fn set_launch_seed_clock(&mut self, launch_seed_clock: u32) {
+        self.launch_seed_clock = Some(launch_seed_clock);
+        self.transitions.push(RunTransition::SetLaunchSeedClock(launch_seed_clock));
     }
     pub fn rng_found(&self) -> bool {
         self.rng_found
     }
+    pub fn take_transitions(&mut self) -> Vec<RunTransition> {
+        mem::take(&mut self.transitions)
+    }
 }
 
 // Context shared across all tools
 pub struct MainContext<'a> {
-    // Main program config
-    pub config: &'a Config,
+    // Main program config (replaced when config.json changes), and the notice currently shown at the bottom of the screen
+    pub config: Rc<Config>,
+    pub config_watcher: ConfigWatcher,
+    pub notice: Option<Notice>,
 
     // Window/rendering
     pub sdl_context: &'a Sdl,
@@ -97,19 +129,33 @@ pub struct MainContext<'a> {
     // Panic handling
     pub panic_occurred: Arc<AtomicBool>,
 
-    // Server communication
-    pub hotkey_receiver: &'a Receiver<u32>,
+    // Server communication. Hotkeys and screenshots from the server get passed along to programs once per frame (see poll_events).
+    pub hotkey_receiver: Receiver<u32>,
+    pub hotkey_forwarder: Sender<u32>,
+    pub server_hotkey_receiver: &'a Receiver<u32>,
     pub message_to_send_sender: &'a Sender<MessageToSend>,
     pub screenshot_data: Arc<Mutex<Vec<ScreenshotData>>>,
+    pub server_screenshot_data: Arc<Mutex<Vec<ScreenshotData>>>,
     pub server_connected: Arc<AtomicBool>,
 
+    // Screenshot files being dropped onto the window, and ones given on the command line (imported upon entering a program that uses screenshots)
+    pub dropped_files: Vec<PathBuf>,
+    pub launch_screenshots: Vec<PathBuf>,
+
     // Error message for when in the error sub-program
     pub error_message: &'static str,
     pub error_return_to: SubProgram,
     pub error_returning: bool,
 
     // Main run context
-    pub run_context: RunContext
+    pub run_context: RunContext,
+
+    // Recovery of the RNG seed from pasted outputs, kept between visits to the RNG override program (so seeds are only loaded once)
+    pub rng_recovery: Option<program_rng_override::RngRecovery>,
+
+    // Session being recorded or replayed, if any, and the number of frames run so far (across all programs)
+    pub session: Session,
+    pub frame: u32
 }
 impl MainContext<'_> {
     // Ignores messages, but returns whether a reset hotkey was pressed or not
@@ -129,6 +175,272 @@ impl MainContext<'_> {
 
         reset
     }
+
+    // Reloads the config if config.json changed, applying window settings right away.
+    // Other settings apply the next time a program is entered (or right away, for the few that are read continuously).
+    pub fn poll_config_reload(&mut self) {
+        // When replaying, config changes come from the session instead
+        if matches!(self.session, Session::Replaying(_)) {
+            return;
+        }
+        let Some(result) = self.config_watcher.poll(self.config.active_profile.as_deref()) else { return };
+        match result {
+            Ok(config) => self.replace_config(config, "Reloaded config.json."),
+            Err(problems) => self.show_config_problems(&problems, "config.json wasn't reloaded")
+        }
+    }
+
+    // Switches to another profile from the config, starting a new run
+    pub fn switch_profile(&mut self, profile: &str) {
+        // When replaying, the profile's config comes from the session instead
+        if matches!(self.session, Session::Replaying(_)) {
+            self.run_context.reset();
+            return;
+        }
+        match Config::read(Some(profile)) {
+            Ok(config) => {
+                self.run_context.reset();
+                self.replace_config(config, &format!("Switched to profile \"{}\".", profile));
+            },
+            Err(problems) => self.show_config_problems(&problems, &format!("Couldn't switch to profile \"{}\"", profile))
+        }
+    }
+
+    // Collects files dropped onto the window, importing them as screenshots once the whole drop is complete
+    pub fn handle_drop_event(&mut self, event: &Event) {
+        match event {
+            Event::DropBegin { .. } => self.dropped_files.clear(),
+            Event::DropFile { filename, .. } => self.dropped_files.push(PathBuf::from(filename)),
+            Event::DropComplete { .. } => {
+                let paths = mem::take(&mut self.dropped_files);
+                self.import_screenshots(paths);
+            },
+            _ => {}
+        }
+    }
+
+    // Imports the screenshot files given on the command line, if not done already
+    pub fn import_launch_screenshots(&mut self) {
+        if !self.launch_screenshots.is_empty() {
+            let paths = mem::take(&mut self.launch_screenshots);
+            self.import_screenshots(paths);
+        }
+    }
+
+    // Imports screenshots from image files (in order of file name), as if they were all sent at once by the OBS plugin
+    fn import_screenshots(&mut self, mut paths: Vec<PathBuf>) {
+        paths.sort();
+        let mut screenshots: Vec<ScreenshotData> = Vec::with_capacity(paths.len());
+        for path in &paths {
+            match frame_images::load_screenshot_file(path) {
+                Ok(screenshot) => screenshots.push(screenshot),
+                Err(e) => {
+                    println!("Failed to import screenshot {}: {}", path.display(), e);
+                    self.notice = Some(Notice::new(&format!("Couldn't import screenshot {}:\n{}", path.display(), e), true));
+                    return;
+                }
+            }
+        }
+        if screenshots.is_empty() {
+            return;
+        }
+        println!("Imported {} screenshot(s)", screenshots.len());
+        self.notice = Some(Notice::new(&format!("Imported {} screenshot(s).", screenshots.len()), false));
+        self.deliver_screenshots(screenshots);
+    }
+
+    // Passes screenshots along to programs, recording them if needed
+    fn deliver_screenshots(&mut self, screenshots: Vec<ScreenshotData>) {
+        if screenshots.is_empty() {
+            return;
+        }
+        let mut screenshot_data = self.screenshot_data.lock().unwrap();
+        for screenshot in screenshots {
+            if let Session::Recording(recorder) = &mut self.session {
+                recorder.record(self.frame, SessionRecord::Screenshot(ScreenshotData {
+                    width: screenshot.width,
+                    height: screenshot.height,
+                    stride: screenshot.stride,
+                    data: screenshot.data.clone()
+                }));
+            }
+            screenshot_data.push(screenshot);
+        }
+    }
+
+    fn record(&mut self, event: SessionEvent) {
+        if let Session::Recording(recorder) = &mut self.session {
+            recorder.record(self.frame, SessionRecord::Event(event));
+        }
+    }
+
+    // Polls input events for a new frame, and passes along messages from the server.
+    // When recording, all of these get recorded; when replaying, they're replaced by the ones from the session.
+    pub fn poll_events(&mut self, event_pump: &mut EventPump) -> Vec<Event> {
+        self.handle_run_transitions();
+        self.frame += 1;
+
+        let mut events: Vec<Event> = event_pump.poll_iter().collect();
+        let mut abandoned_search = false;
+        let replayed_records = match &mut self.session {
+            Session::Replaying(replay) if !replay.is_finished() => {
+                abandoned_search = replay.abandon_unpolled_search();
+                Some(replay.next_frame())
+            },
+            _ => None
+        };
+        if abandoned_search {
+            self.report_replay_divergence("a search result was recorded, but no search was running");
+        }
+        if let Some(records) = replayed_records {
+            // Live input is ignored while replaying (other than closing the window), as are messages from the server
+            events.retain(|event| matches!(event, Event::Quit { .. }));
+            for _ in self.server_hotkey_receiver.try_iter() {}
+            self.server_screenshot_data.lock().unwrap().clear();
+
+            let window_id = self.canvas.window().id();
+            let screen_space = ScreenSpace::from_canvas(self.canvas);
+            for record in records {
+                match record {
+                    SessionRecord::Screenshot(screenshot) => self.deliver_screenshots(vec![screenshot]),
+                    SessionRecord::Event(SessionEvent::Hotkey(hotkey)) => _ = self.hotkey_forwarder.send(hotkey),
+                    SessionRecord::Event(SessionEvent::Input(input)) => events.push(input.to_sdl(window_id, &screen_space)),
+                    SessionRecord::Event(SessionEvent::Config { profile, config }) => match session::config_from_event(profile, config) {
+                        Ok(config) => self.replace_config(config, "Replayed config change."),
+                        Err(e) => println!("Failed to replay config change: {}", e)
+                    },
+                    SessionRecord::Event(_) => {}
+                }
+            }
+            if let Session::Replaying(replay) = &self.session {
+                if replay.is_finished() {
+                    println!("Replay finished at frame {}", replay.frame());
+                    self.notice = Some(Notice::new("Replay finished. Input works as usual again.", false));
+                }
+            }
+        } else {
+            let hotkeys: Vec<u32> = self.server_hotkey_receiver.try_iter().collect();
+            for hotkey in hotkeys {
+                self.record(SessionEvent::Hotkey(hotkey));
+                _ = self.hotkey_forwarder.send(hotkey);
+            }
+            let screenshots = mem::take(&mut *self.server_screenshot_data.lock().unwrap());
+            self.deliver_screenshots(screenshots);
+            let screen_space = ScreenSpace::from_canvas(self.canvas);
+            for event in &events {
+                if let Some(input) = InputEvent::from_sdl(event, &screen_space) {
+                    self.record(SessionEvent::Input(input));
+                }
+            }
+            if let Session::Recording(recorder) = &mut self.session {
+                recorder.flush();
+            }
+        }
+        events
+    }
+
+    // Records changes made to the run context during the last frame, or when replaying, checks them against the session
+    fn handle_run_transitions(&mut self) {
+        for transition in self.run_context.take_transitions() {
+            match &mut self.session {
+                Session::None => {},
+                Session::Recording(recorder) => recorder.record(self.frame, SessionRecord::Event(SessionEvent::RunContext(transition))),
+                Session::Replaying(replay) => {
+                    if let Some(difference) = replay.check_transition(&transition) {
+                        self.report_replay_divergence(&difference);
+                    }
+                }
+            }
+        }
+    }
+
+    fn report_replay_divergence(&mut self, difference: &str) {
+        let Session::Replaying(replay) = &self.session else { return };
+        let frame = replay.frame();
+        println!("Replay diverged from the recorded session at frame {}: {}", frame, difference);
+        self.notice = Some(Notice::new(&format!("Replay diverged from the recorded session at frame {} (see console output).", frame), true));
+    }
+
+    // Returns the result of a search once it has finished (see SearchJob::poll_result).
+    // When recording, the result arriving gets recorded; when replaying, the result is held back until the point it arrived
+    // in the session, and the replay waits for it if the search takes longer this time.
+    // A search that can't finish (e.g. when the GPU backend was chosen but failed to preload) gets reported in a notice.
+    pub fn poll_search_result<'j, P: Send + 'static, R: Default + Send + 'static>(&mut self, compute_job: &'j mut SearchJob<P, R>) -> Option<MutexGuard<'j, R>> {
+        if let Some(e) = compute_job.poll_error() {
+            println!("Search failed: {}", e);
+            self.notice = Some(Notice::new(&format!("Search failed: {}.\nSetting compute_backend to \"auto\" or \"cpu\" in config.json may help.", e), true));
+            return None;
+        }
+        let Session::Replaying(replay) = &mut self.session else {
+            let result = compute_job.poll_result()?;
+            self.record(SessionEvent::SearchFinished);
+            return Some(result);
+        };
+        if replay.is_finished() {
+            return compute_job.poll_result();
+        }
+        replay.poll_search();
+        if !replay.search_result_due() {
+            return None;
+        }
+        if !compute_job.is_searching() {
+            replay.take_search_result();
+            self.report_replay_divergence("a search result was recorded, but no search was running");
+            return None;
+        }
+        let result = compute_job.poll_result()?;
+        replay.take_search_result();
+        Some(result)
+    }
+
+    // Reads the seed clock (see seed_timing), or when replaying, the reading from the session
+    pub fn read_seed_clock(&mut self) -> Option<u32> {
+        if let Session::Replaying(replay) = &mut self.session {
+            if !replay.is_finished() {
+                return replay.next_seed_clock();
+            }
+        }
+        let clock = seed_timing::current_seed_clock();
+        self.record(SessionEvent::SeedClock(clock));
+        clock
+    }
+
+    // Reads text from the clipboard, or when replaying, the text from the session
+    pub fn read_clipboard_text(&mut self) -> Option<String> {
+        if let Session::Replaying(replay) = &mut self.session {
+            if !replay.is_finished() {
+                return replay.next_clipboard_text();
+            }
+        }
+        let text = self.sdl_context.video().ok().and_then(|video| video.clipboard().clipboard_text().ok());
+        self.record(SessionEvent::ClipboardText(text.clone()));
+        text
+    }
+
+    fn replace_config(&mut self, config: Config, notice_text: &str) {
+        apply_window_config(self.canvas.window_mut(), &config, &self.config);
+
+        // RNG found for the run doesn't apply to a different runner version, so start a new run
+        let mut notice_text = notice_text.to_string();
+        if config.runner_version != self.config.runner_version {
+            self.run_context.reset();
+            notice_text += " Started a new run, as runner_version changed.";
+        }
+        if config.server_port != self.config.server_port {
+            notice_text += " Restart the tool to use the new server_port.";
+        }
+        self.notice = Some(Notice::new(&notice_text, false));
+        self.config = Rc::new(config);
+        self.record(SessionEvent::from_config(&self.config));
+    }
+
+    fn show_config_problems(&mut self, problems: &[ConfigProblem], notice_text: &str) {
+        println!("{}:", notice_text);
+        for problem in problems {
+            println!("- {}", problem);
+        }
+        self.notice = Some(Notice::new(&format!("{}, as config.json has problems (see console output). First problem:\n{}", notice_text, problems[0]), true));
+    }
 }
 
 struct PanicParameters {
@@ -182,6 +494,12 @@ fn remove_info_from_path(path: String) -> String {
 }
 
 fn main() {
+    // Run a subcommand without opening a window, if one was given
+    let args: Vec<String> = env::args().collect();
+    if let Some(exit_code) = cli::run(&args) {
+        process::exit(exit_code);
+    }
+
     // Handle panics somewhat gracefully
     let existing_hook = panic::take_hook();
     let panic_parameters = Arc::new(Mutex::new(PanicParameters {
@@ -228,29 +546,6 @@ fn main() {
         existing_hook(info);
     }));
 
-    // Load config
-    let config = Config::read().expect("Failed to load config");
-
-    // Start server
-    let server_config = config.clone();
-    let server_end_signal = Arc::new(AtomicBool::new(false));
-    let server_end_signal_thread = server_end_signal.clone();
-    let server_connected = Arc::new(AtomicBool::new(false));
-    let server_connected_thread = server_connected.clone();
-    let screenshot_data: Arc<Mutex<Vec<ScreenshotData>>> = Arc::new(Mutex::new(Vec::with_capacity(100)));
-    let screenshot_data_thread = screenshot_data.clone();
-    let (hotkey_sender, hotkey_receiver) = mpsc::channel::<u32>();
-    let (message_to_send_sender, message_to_send_receiver) = mpsc::channel::<MessageToSend>();
-    let server_join_handle = thread::spawn(move || {
-        server::run_server(
-            &server_config,
-            Arc::clone(&server_end_signal_thread), 
-            Arc::clone(&server_connected_thread),
-            Arc::clone(&screenshot_data_thread),
-            hotkey_sender,
-            message_to_send_receiver);
-    });
-
     // Initialize SDL and its video subsystem
     let sdl_context = sdl3::init().expect("Failed to initialize SDL");
     let video_subsystem = sdl_context.video().expect("Failed to get SDL video subsystem");
@@ -275,7 +570,7 @@ fn main() {
     }
 
     // Start window and graphics for GUI
-    let mut window = video_subsystem.window("Dust Manipulator", default_width, default_height)
+    let window = video_subsystem.window("Dust Manipulator", default_width, default_height)
         .position_centered()
         .resizable()
         .hidden()
@@ -284,43 +579,145 @@ fn main() {
     if let Ok(mut panic_parameters) = panic_parameters.lock() {
         panic_parameters.error_window = window.raw();
     }
-    if config.window_always_on_top {
-        if !window_set_always_on_top(&mut window, true) {
-            println!("Failed to set window to be always on top");
-        }
-    }
-    if config.window_unfocusable_by_default {
-        if !window_set_focusable(&mut window, false) {
-            println!("Failed to set window to be unfocusable");
+    let mut canvas = window.into_canvas();
+    let texture_creator = canvas.texture_creator();
+
+    // Options for the GUI from the command line
+    let launch_screenshots: Vec<PathBuf> = args.iter().enumerate()
+        .filter(|(_, arg)| *arg == "--screenshot")
+        .filter_map(|(i, _)| args.get(i + 1).map(PathBuf::from))
+        .collect();
+    let profile = args.iter().position(|arg| arg == "--profile").and_then(|i| args.get(i + 1));
+    let record_path = args.iter().position(|arg| arg == "--record").and_then(|i| args.get(i + 1));
+    let replay_path = args.iter().position(|arg| arg == "--replay").and_then(|i| args.get(i + 1));
+    let mut window_shown = false;
+
+    // Open the session to replay, if any, which also has the config to use
+    let mut replay: Option<SessionReplay> = None;
+    let replay_config = match replay_path {
+        Some(path) => match SessionReplay::open(Path::new(path)) {
+            Ok((session_replay, config)) => {
+                replay = Some(session_replay);
+                Some(config)
+            },
+            Err(e) => {
+                println!("Failed to open session {} for replaying: {}", path, e);
+                if let (Ok(title), Ok(message)) = (CString::new("Error"), CString::new(format!("Failed to open session file for replaying:\n\n{}", e))) {
+                    unsafe { SDL_ShowSimpleMessageBox(SDL_MESSAGEBOX_ERROR, title.as_ptr(), message.as_ptr(), canvas.window().raw()) };
+                }
+                if let Ok(mut panic_parameters) = panic_parameters.lock() {
+                    panic_parameters.error_window = ptr::null_mut();
+                }
+                return;
+            }
+        },
+        None => None
+    };
+
+    // Load config (with the profile chosen on the command line, if any), showing any problems with it until they're fixed (or the tool is closed)
+    let config = match replay_config {
+        Some(config) => config,
+        None => loop {
+            match Config::read(profile.map(String::as_str)) {
+                Ok(config) => break config,
+                Err(problems) => {
+                    println!("Failed to load config:");
+                    for problem in &problems {
+                        println!("- {}", problem);
+                    }
+                    if !window_shown {
+                        canvas.window_mut().show();
+                        window_shown = true;
+                    }
+                    if !program_error::run_config_problems(&sdl_context, &font, &mut canvas, &texture_creator, &problems) {
+                        if let Ok(mut panic_parameters) = panic_parameters.lock() {
+                            panic_parameters.error_window = ptr::null_mut();
+                        }
+                        return;
+                    }
+                }
+            }
         }
-    }
-    if config.window_opacity != 1.0 {
-        if let Err(e) = window.set_opacity(config.window_opacity) {
-            println!("Failed to set window opacity: {}", e);
+    };
+
+    // Write back an upgraded config file (with every setting filled in), if requested
+    if args.iter().any(|arg| arg == "--upgrade-config") {
+        match Config::upgrade_file() {
+            Ok(()) => println!("Upgraded config.json to the latest layout"),
+            Err(e) => println!("Failed to upgrade config.json: {}", e)
         }
     }
-    let mut canvas = window.into_canvas();
-    let texture_creator = canvas.texture_creator();
+
+    // Apply window settings from config
+    apply_window_config(canvas.window_mut(), &config, &Config::default());
+
+    // Start server
+    let server_config = config.clone();
+    let server_end_signal = Arc::new(AtomicBool::new(false));
+    let server_end_signal_thread = server_end_signal.clone();
+    let server_connected = Arc::new(AtomicBool::new(false));
+    let server_connected_thread = server_connected.clone();
+    let server_screenshot_data: Arc<Mutex<Vec<ScreenshotData>>> = Arc::new(Mutex::new(Vec::with_capacity(100)));
+    let server_screenshot_data_thread = server_screenshot_data.clone();
+    let (hotkey_sender, server_hotkey_receiver) = mpsc::channel::<u32>();
+    let (message_to_send_sender, message_to_send_receiver) = mpsc::channel::<MessageToSend>();
+    let server_join_handle = thread::spawn(move || {
+        server::run_server(
+            &server_config,
+            Arc::clone(&server_end_signal_thread), 
+            Arc::clone(&server_connected_thread),
+            Arc::clone(&server_screenshot_data_thread),
+            hotkey_sender,
+            message_to_send_receiver);
+    });
 
     // Create main context
+    let (hotkey_forwarder, hotkey_receiver) = mpsc::channel::<u32>();
     let mut main_context = MainContext {
-        config: &config,
+        config: Rc::new(config),
+        config_watcher: ConfigWatcher::new(),
+        notice: None,
         sdl_context: &sdl_context,
         font: &font,
         canvas: &mut canvas,
         texture_creator: &texture_creator,
-        window_shown: false,
+        window_shown,
         panic_occurred,
-        hotkey_receiver: &hotkey_receiver,
+        hotkey_receiver,
+        hotkey_forwarder,
+        server_hotkey_receiver: &server_hotkey_receiver,
         message_to_send_sender: &message_to_send_sender,
         server_connected,
-        screenshot_data,
+        screenshot_data: Arc::new(Mutex::new(Vec::with_capacity(100))),
+        server_screenshot_data,
+        dropped_files: Vec::new(),
+        launch_screenshots,
         error_message: "",
         error_return_to: SubProgram::None,
         error_returning: false,
-        run_context: RunContext::new()
+        run_context: RunContext::new(),
+        rng_recovery: None,
+        session: Session::None,
+        frame: 0
     };
 
+    // Start replaying or recording a session, if requested
+    if let Some(replay) = replay {
+        main_context.session = Session::Replaying(replay);
+        main_context.notice = Some(Notice::new("Replaying session...", false));
+    } else if let Some(path) = record_path {
+        match SessionRecorder::create(Path::new(path)) {
+            Ok(recorder) => {
+                main_context.session = Session::Recording(recorder);
+                main_context.record(SessionEvent::from_config(&main_context.config));
+            },
+            Err(e) => {
+                println!("Failed to start recording session to {}: {}", path, e);
+                main_context.notice = Some(Notice::new(&format!("Couldn't start recording session: {}", e), true));
+            }
+        }
+    }
+
     // Run sub-programs
     let mut program = SubProgram::ProgramSelector;
     'running: loop {
@@ -335,7 +732,8 @@ fn main() {
             SubProgram::NamingSeedSearch => program_naming_seed_search::run(&mut main_context),
             SubProgram::DogiManip => program_dogi_manip::run(&mut main_context),
             SubProgram::Error => program_error::run(&mut main_context),
-            SubProgram::RNGOverride => program_rng_override::run(&mut main_context)
+            SubProgram::RNGOverride => program_rng_override::run(&mut main_context),
+            SubProgram::ProfileSelector => program_profile_selector::run(&mut main_context)
         }
     }
 
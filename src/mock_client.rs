@@ -0,0 +1,338 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::frame_images;
+use crate::server::{self, MessageHeader, ScreenshotData};
+
+// How long to keep retrying the connection (e.g. while the tool is still starting up)
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How long to wait for the tool to acknowledge the hello message
+const HELLO_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How often the receiving thread checks whether it should stop
+const RECEIVE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Size of messages sent by the tool (message code, then data)
+const RECEIVED_MESSAGE_SIZE: usize = 8;
+
+// A single step of a mock client script
+pub enum MockStep {
+    // Sends a batch of screenshots, like the plugin does after a hotkey press
+    Screenshots(Vec<ScreenshotData>),
+    // Sends a hotkey press (1 to 5)
+    Hotkey(u32),
+    // Waits for the given number of milliseconds
+    Wait(u32),
+    // Disconnects from the tool
+    Goodbye
+}
+
+// Loads a mock client script, made up of one step per line:
+//   screenshots <file> [<file> ...]
+//   hotkey <1-5>
+//   wait <milliseconds>
+//   goodbye
+// Empty lines and lines starting with '#' are ignored. Screenshot paths are relative to the script's folder.
+pub fn load_script(path: &Path) -> Result<Vec<MockStep>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse_script(&contents, path.parent().unwrap_or(Path::new(".")))
+}
+
+pub fn parse_script(contents: &str, base_dir: &Path) -> Result<Vec<MockStep>, String> {
+    let mut steps = Vec::new();
+    for (line_index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: &str| format!("Line {}: {}", line_index + 1, message);
+        if matches!(steps.last(), Some(MockStep::Goodbye)) {
+            return Err(error("no steps are allowed after goodbye"));
+        }
+
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap();
+        let arguments: Vec<&str> = words.collect();
+        let step = match (command, arguments.as_slice()) {
+            ("screenshots", []) => return Err(error("expected at least one screenshot file")),
+            ("screenshots", files) => {
+                let screenshots = files.iter().map(|file| {
+                    let path = base_dir.join(file);
+                    frame_images::load_screenshot_file(&path).map_err(|e| error(&format!("failed to load {}: {}", path.display(), e)))
+                }).collect::<Result<_, _>>()?;
+                MockStep::Screenshots(screenshots)
+            },
+            ("hotkey", [number]) => match number.parse() {
+                Ok(number @ 1..=5) => MockStep::Hotkey(number),
+                _ => return Err(error(&format!("invalid hotkey \"{}\" (expected 1 to 5)", number)))
+            },
+            ("wait", [milliseconds]) => MockStep::Wait(milliseconds.parse().map_err(|_| error(&format!("invalid duration \"{}\"", milliseconds)))?),
+            ("goodbye", []) => MockStep::Goodbye,
+            ("hotkey" | "wait" | "goodbye", _) => return Err(error(&format!("wrong number of arguments for {}", command))),
+            _ => return Err(error(&format!("unknown step \"{}\"", command)))
+        };
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+// A message the tool sent to the mock client
+pub struct ReceivedMessage {
+    pub time_ms: u64,
+    pub message_code: u32,
+    pub data: u32
+}
+impl ReceivedMessage {
+    pub fn to_json(&self) -> Value {
+        match self.message_code {
+            server::MESSAGE_CODE_SCREENSHOT_MODE => json!({
+                "time_ms": self.time_ms,
+                "message": "screenshot_mode",
+                "single_only": self.data != 0
+            }),
+            server::MESSAGE_CODE_SCREENSHOT_START_DELAY => json!({
+                "time_ms": self.time_ms,
+                "message": "screenshot_start_delay",
+                "delay_ms": self.data
+            }),
+            _ => json!({
+                "time_ms": self.time_ms,
+                "message": "unknown",
+                "message_code": format!("{:#010x}", self.message_code),
+                "data": self.data
+            })
+        }
+    }
+}
+
+// Stands in for the OBS plugin, connecting to the tool's server to send screenshots and hotkeys
+pub struct MockClient {
+    stream: TcpStream,
+    said_goodbye: bool,
+    received: Vec<ReceivedMessage>,
+    received_receiver: Receiver<ReceivedMessage>,
+    receive_end_signal: Arc<AtomicBool>,
+    receive_thread: Option<JoinHandle<()>>
+}
+impl MockClient {
+    // Connects to the tool on the given port, and completes the hello handshake
+    pub fn connect(port: u16) -> Result<Self, String> {
+        let address = SocketAddr::from(([127, 0, 0, 1], port));
+        let connect_start = Instant::now();
+        let stream = loop {
+            match TcpStream::connect(address) {
+                Ok(stream) => break stream,
+                Err(e) if connect_start.elapsed() >= CONNECT_TIMEOUT => return Err(format!("Failed to connect to {}: {}", address, e)),
+                Err(_) => thread::sleep(Duration::from_millis(100))
+            }
+        };
+        _ = stream.set_nodelay(true);
+        stream.set_read_timeout(Some(RECEIVE_POLL_INTERVAL)).map_err(|e| e.to_string())?;
+
+        let (hello_ack_sender, hello_ack_receiver) = mpsc::channel();
+        let (received_sender, received_receiver) = mpsc::channel();
+        let receive_end_signal = Arc::new(AtomicBool::new(false));
+        let receive_stream = stream.try_clone().map_err(|e| e.to_string())?;
+        let receive_thread = {
+            let receive_end_signal = receive_end_signal.clone();
+            thread::spawn(move || receive_messages(receive_stream, receive_end_signal, hello_ack_sender, received_sender))
+        };
+        let mut client = MockClient {
+            stream,
+            said_goodbye: false,
+            received: Vec::new(),
+            received_receiver,
+            receive_end_signal,
+            receive_thread: Some(receive_thread)
+        };
+
+        client.send(&MessageHeader::new(server::MESSAGE_CODE_HELLO).to_bytes())?;
+        if hello_ack_receiver.recv_timeout(HELLO_ACK_TIMEOUT).is_err() {
+            return Err("The tool didn't acknowledge the hello message".to_string());
+        }
+        Ok(client)
+    }
+
+    fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        self.stream.write_all(data).map_err(|e| format!("Failed to send to the tool: {}", e))
+    }
+
+    pub fn run_step(&mut self, step: &MockStep) -> Result<(), String> {
+        if self.said_goodbye {
+            return Err("Already disconnected from the tool".to_string());
+        }
+        match step {
+            MockStep::Screenshots(screenshots) => {
+                for (index, screenshot) in screenshots.iter().enumerate() {
+                    let header = MessageHeader {
+                        message_code: server::MESSAGE_CODE_SCREENSHOT,
+                        screenshot_has_more: if index + 1 < screenshots.len() { 1 } else { 0 },
+                        screenshot_width: screenshot.width,
+                        screenshot_height: screenshot.height,
+                        screenshot_stride: screenshot.stride,
+                        screenshot_bits_per_pixel: 32
+                    };
+                    self.send(&header.to_bytes())?;
+                    self.send(&screenshot.data[..(screenshot.stride * screenshot.height) as usize])?;
+                }
+            },
+            MockStep::Hotkey(number) => {
+                self.send(&MessageHeader::new(server::MESSAGE_CODE_HOTKEY_1 + number - 1).to_bytes())?;
+            },
+            MockStep::Wait(milliseconds) => {
+                thread::sleep(Duration::from_millis(*milliseconds as u64));
+            },
+            MockStep::Goodbye => {
+                self.send(&MessageHeader::new(server::MESSAGE_CODE_GOODBYE).to_bytes())?;
+                self.said_goodbye = true;
+            }
+        }
+        Ok(())
+    }
+
+    // Returns every message received from the tool so far, logging any new ones
+    pub fn poll_received(&mut self) -> &[ReceivedMessage] {
+        for message in self.received_receiver.try_iter() {
+            eprintln!("Received from tool: {}", message.to_json());
+            self.received.push(message);
+        }
+        &self.received
+    }
+
+    // Waits for any last messages from the tool, then disconnects (if the script didn't already), returning everything received
+    pub fn finish(mut self, linger: Duration) -> Result<Vec<ReceivedMessage>, String> {
+        thread::sleep(linger);
+        if !self.said_goodbye {
+            self.run_step(&MockStep::Goodbye)?;
+        }
+        self.poll_received();
+        Ok(std::mem::take(&mut self.received))
+    }
+}
+impl Drop for MockClient {
+    fn drop(&mut self) {
+        self.receive_end_signal.store(true, Ordering::Relaxed);
+        _ = self.stream.shutdown(Shutdown::Both);
+        if let Some(receive_thread) = self.receive_thread.take() {
+            _ = receive_thread.join();
+        }
+    }
+}
+
+// Reads messages from the tool until the connection closes (or the client is dropped).
+// The first 4 bytes are the hello acknowledgment; everything after that is a message code followed by its data.
+fn receive_messages(mut stream: TcpStream, end_signal: Arc<AtomicBool>, hello_ack_sender: Sender<()>, received_sender: Sender<ReceivedMessage>) {
+    let start = Instant::now();
+    let mut pending: Vec<u8> = Vec::new();
+    let mut got_hello_ack = false;
+    let mut buffer = [0; 256];
+    while !end_signal.load(Ordering::Relaxed) {
+        match stream.read(&mut buffer) {
+            Ok(0) => return,
+            Ok(size) => pending.extend_from_slice(&buffer[..size]),
+            Err(ref e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(_) => return
+        }
+
+        if !got_hello_ack && pending.len() >= 4 {
+            let message_code = u32::from_be_bytes(pending[0..4].try_into().unwrap());
+            if message_code != server::MESSAGE_CODE_HELLO_ACK {
+                eprintln!("Received unexpected hello acknowledgment ({:#010x})", message_code);
+                return;
+            }
+            pending.drain(..4);
+            got_hello_ack = true;
+            _ = hello_ack_sender.send(());
+        }
+        if got_hello_ack {
+            while pending.len() >= RECEIVED_MESSAGE_SIZE {
+                let message: Vec<u8> = pending.drain(..RECEIVED_MESSAGE_SIZE).collect();
+                _ = received_sender.send(ReceivedMessage {
+                    time_ms: start.elapsed().as_millis() as u64,
+                    message_code: u32::from_be_bytes(message[0..4].try_into().unwrap()),
+                    data: u32::from_be_bytes(message[4..8].try_into().unwrap())
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+
+    use crate::config::Config;
+    use crate::server::MessageToSend;
+
+    use super::*;
+
+    fn test_screenshot(fill: u8) -> ScreenshotData {
+        ScreenshotData { width: 640, height: 480, stride: 640 * 4, data: vec![fill; 640 * 4 * 480] }
+    }
+
+    #[test]
+    fn parses_scripts() {
+        let steps = parse_script("# Press a hotkey\nhotkey 2\n\nwait 250\ngoodbye\n", Path::new(".")).unwrap();
+        assert!(matches!(steps.as_slice(), [MockStep::Hotkey(2), MockStep::Wait(250), MockStep::Goodbye]));
+
+        assert!(parse_script("hotkey 6", Path::new(".")).is_err());
+        assert!(parse_script("screenshots", Path::new(".")).is_err());
+        assert!(parse_script("wait", Path::new(".")).is_err());
+        assert!(parse_script("goodbye\nhotkey 1", Path::new(".")).is_err());
+        assert!(parse_script("jump", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn talks_to_server() {
+        // Find a free port for the server
+        let port = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap().local_addr().unwrap().port();
+        let config = Config { server_port: port, ..Config::default() };
+
+        let end_signal = Arc::new(AtomicBool::new(false));
+        let screenshot_data = Arc::new(Mutex::new(Vec::new()));
+        let (hotkey_sender, hotkey_receiver) = mpsc::channel();
+        let (messages_to_send_sender, messages_to_send_receiver) = mpsc::channel();
+        messages_to_send_sender.send(MessageToSend::new_screenshot_mode(true)).unwrap();
+        messages_to_send_sender.send(MessageToSend::new_screenshot_start_delay(150)).unwrap();
+        let server_thread = {
+            let end_signal = end_signal.clone();
+            let screenshot_data = screenshot_data.clone();
+            thread::spawn(move || server::run_server(&config, end_signal, Arc::new(AtomicBool::new(false)), screenshot_data,
+                                                     hotkey_sender, messages_to_send_receiver))
+        };
+
+        let mut client = MockClient::connect(port).unwrap();
+        client.run_step(&MockStep::Hotkey(3)).unwrap();
+        client.run_step(&MockStep::Screenshots(vec![test_screenshot(0x10), test_screenshot(0x10), test_screenshot(0x20)])).unwrap();
+
+        // Wait for the server to handle everything before shutting it down
+        let mut hotkeys = Vec::new();
+        let wait_start = Instant::now();
+        while (client.poll_received().len() < 2 || hotkeys.is_empty() || screenshot_data.lock().unwrap().is_empty()) && wait_start.elapsed() < Duration::from_secs(5) {
+            hotkeys.extend(hotkey_receiver.try_iter());
+            thread::sleep(Duration::from_millis(10));
+        }
+        let received = client.finish(Duration::ZERO).unwrap();
+        end_signal.store(true, Ordering::Relaxed);
+        server_thread.join().unwrap();
+
+        let received: Vec<(u32, u32)> = received.iter().map(|message| (message.message_code, message.data)).collect();
+        assert_eq!(received, [(server::MESSAGE_CODE_SCREENSHOT_MODE, 1), (server::MESSAGE_CODE_SCREENSHOT_START_DELAY, 150)]);
+        assert_eq!(hotkeys, [2]);
+
+        // Duplicate screenshots get dropped by the server
+        let screenshot_data = screenshot_data.lock().unwrap();
+        assert_eq!(screenshot_data.len(), 2);
+        assert_eq!(screenshot_data[1].data[0], 0x20);
+    }
+}
@@ -1,15 +1,15 @@
-use std::{f32, ptr};
+use std::{f32, ptr, sync::atomic::Ordering, time::{Duration, Instant}};
 
-use sdl3::{pixels::Color, rect::Rect, render::{Canvas, FPoint, FRect, Texture}, video::Window};
+use sdl3::{event::Event, keyboard::Keycode, pixels::Color, rect::{Point, Rect}, render::{Canvas, FPoint, FRect, ScaleMode, Texture}, video::Window};
 use sdl3_sys::{pixels::SDL_FColor, rect::SDL_FPoint, render::{SDL_RenderGeometry, SDL_Vertex}, timer::{SDL_DelayPrecise, SDL_GetTicksNS}};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::MainContext;
 
 pub const DEFAULT_SCREEN_WIDTH: u32 = 640;
 pub const DEFAULT_SCREEN_HEIGHT: u32 = 480;
 
-#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq)]
 pub enum SubProgram {
     None,
     ProgramSelector,
@@ -18,7 +18,8 @@ pub enum SubProgram {
     NamingSeedSearch,
     DogiManip,
     Error,
-    RNGOverride
+    RNGOverride,
+    ProfileSelector
 }
 
 pub struct ScreenSpace {
@@ -27,7 +28,10 @@ pub struct ScreenSpace {
 }
 impl ScreenSpace {
     pub fn new(main_context: &MainContext) -> Self {
-        let output_size = main_context.canvas.output_size().unwrap_or_else(|_| (DEFAULT_SCREEN_WIDTH, DEFAULT_SCREEN_HEIGHT));
+        Self::from_canvas(main_context.canvas)
+    }
+    pub fn from_canvas(canvas: &Canvas<Window>) -> Self {
+        let output_size = canvas.output_size().unwrap_or_else(|_| (DEFAULT_SCREEN_WIDTH, DEFAULT_SCREEN_HEIGHT));
         let scale_amount = if (output_size.0 as f32) < (output_size.1 as f32 * ((DEFAULT_SCREEN_WIDTH as f32) / (DEFAULT_SCREEN_HEIGHT as f32))) { 
             (output_size.0 as f32) / (DEFAULT_SCREEN_WIDTH as f32) 
         } else { 
@@ -122,6 +126,48 @@ pub fn draw_connected_text(main_context: &mut MainContext, screen_space: &Screen
     Ok(())
 }
 
+// How long notices stay on screen (unless they're errors, which stay until replaced by another notice)
+const NOTICE_DURATION: Duration = Duration::from_secs(3);
+
+pub struct Notice {
+    text: String,
+    is_error: bool,
+    shown_at: Instant
+}
+impl Notice {
+    pub fn new(text: &str, is_error: bool) -> Self {
+        Notice {
+            text: text.to_string(),
+            is_error,
+            shown_at: Instant::now()
+        }
+    }
+}
+
+pub fn draw_notice(main_context: &mut MainContext, screen_space: &ScreenSpace) -> Result<(), &'static str> {
+    let Some(notice) = &main_context.notice else { return Ok(()) };
+    if !notice.is_error && notice.shown_at.elapsed() >= NOTICE_DURATION {
+        main_context.notice = None;
+        return Ok(());
+    }
+    let text_to_show = notice.text.clone();
+    let text_color = if notice.is_error {
+        Color::RGB(255, 64, 64)
+    } else {
+        Color::RGB(0, 255, 0)
+    };
+    main_context.font.draw_text_bg(main_context, 
+        &text_to_show, 
+        screen_space.center_x(), screen_space.yoffset() + screen_space.height() - (8.0 * screen_space.scale()),
+        0.5, 1.0,
+        600,
+        screen_space.scale(),
+        text_color,
+        Color::RGBA(0, 0, 0, 192),
+        4.0 * screen_space.scale())?;
+    Ok(())
+}
+
 pub struct FrameTimer {
     frame_start_time: u64,
     target_fps: u32
@@ -147,6 +193,134 @@ impl FrameTimer {
     }
 }
 
+struct MenuItem<'a> {
+    pub rect: Rect,
+    pub texture: Texture<'a>
+}
+
+pub enum MenuResult {
+    // The item at the given index was clicked on, or selected and confirmed with Return
+    Chosen(usize),
+    // Escape was pressed
+    Back,
+    // The menu was left for the given sub-program (when quitting, after a thread error, or after a reset)
+    Exit(SubProgram)
+}
+
+// Runs a menu of the given items (selectable with the mouse or arrow keys), until one is chosen or the menu is left.
+// Anything else on the screen gets drawn by draw_extra, each frame.
+pub fn run_menu<'a>(main_context: &mut MainContext<'a>, labels: &[String], draw_extra: impl Fn(&mut MainContext<'a>, &ScreenSpace)) -> MenuResult {
+    let mut selection_index: i32 = -1;
+
+    // Render text for all the options
+    let mut menu_items: Vec<MenuItem> = Vec::with_capacity(labels.len());
+    let mut curr_y = 8;
+    for label in labels {
+        let surface = main_context.font.render_text(label, Color::RGB(255, 255, 255)).expect("Failed to render text to surface");
+        let mut texture = Texture::from_surface(&surface, main_context.texture_creator).expect("Failed to create texture from surface");
+        texture.set_scale_mode(ScaleMode::Nearest);
+        let menu_item = MenuItem {
+            rect: Rect::new(8, curr_y, texture.width() * 2, texture.height() * 2),
+            texture
+        };
+        curr_y += 4 + menu_item.rect.h;
+        menu_items.push(menu_item);
+    }
+
+    let mut event_pump = main_context.sdl_context.event_pump().unwrap();
+    loop {
+        // Handle thread errors
+        if main_context.panic_occurred.load(Ordering::Relaxed) {
+            return MenuResult::Exit(SubProgram::None);
+        }
+
+        // Pick up any changes to the config
+        main_context.poll_config_reload();
+
+        let frame_timer = FrameTimer::start(30);
+        let screen_space = ScreenSpace::new(main_context);
+
+        main_context.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        main_context.canvas.set_blend_mode(sdl3::render::BlendMode::Blend);
+        main_context.canvas.clear();
+        for event in main_context.poll_events(&mut event_pump) {
+            match event {
+                Event::Quit {..} => {
+                    return MenuResult::Exit(SubProgram::None);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    return MenuResult::Back;
+                },
+                Event::MouseMotion { x, y, .. } => {
+                    if let Some(i) = menu_items.iter().position(|item| screen_space.rect_world_to_screen(item.rect).contains_point(Point::new(x as i32, y as i32))) {
+                        selection_index = i as i32;
+                    }
+                },
+                Event::MouseButtonDown { x, y, .. } => {
+                    selection_index = menu_items.iter()
+                        .position(|item| screen_space.rect_world_to_screen(item.rect).contains_point(Point::new(x as i32, y as i32)))
+                        .map_or(-1, |i| i as i32);
+                    if selection_index >= 0 {
+                        return MenuResult::Chosen(selection_index as usize);
+                    }
+                },
+                Event::KeyDown { keycode, .. } => {
+                    if menu_items.is_empty() {
+                        continue;
+                    }
+                    match keycode {
+                        Some(Keycode::Up) => {
+                            selection_index = if selection_index <= 0 { menu_items.len() as i32 - 1 } else { selection_index - 1 };
+                        },
+                        Some(Keycode::Down) => {
+                            selection_index = (selection_index + 1) % (menu_items.len() as i32);
+                        },
+                        Some(Keycode::Return) if selection_index >= 0 => {
+                            return MenuResult::Chosen(selection_index as usize);
+                        },
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Draw the menu items
+        for (i, item) in menu_items.iter_mut().enumerate() {
+            if selection_index == i as i32 {
+                item.texture.set_color_mod(255, 255, 0);
+            } else {
+                item.texture.set_color_mod(255, 255, 255);
+            }
+            _ = main_context.canvas.copy(&item.texture, rect_from_texture(&item.texture), screen_space.rect_world_to_screen(item.rect));
+        }
+
+        // Draw other text
+        draw_extra(main_context, &screen_space);
+
+        // Draw notice, if any
+        _ = draw_notice(main_context, &screen_space);
+
+        // Present latest canvas
+        main_context.canvas.present();
+
+        // Ignore any server messages, except resets
+        if main_context.ignore_server_messages_except_reset() {
+            main_context.run_context.reset();
+            return MenuResult::Exit(main_context.config.reset_return_to);
+        }
+
+        // Show window, if not already done
+        if !main_context.window_shown {
+            main_context.canvas.window_mut().show();
+            main_context.window_shown = true;
+        }
+
+        // Sleep until next frame
+        frame_timer.end_and_sleep();
+    }
+}
+
 pub fn rect_to_frect(rect: Rect) -> FRect {
     FRect::new(rect.x as f32, rect.y as f32, rect.w as f32, rect.h as f32)
 }
@@ -1,9 +1,8 @@
-use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread};
+use std::{rc::Rc, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}};
 
-use defer_rs::defer;
 use sdl3::{event::Event, keyboard::Keycode, mouse::MouseButton, pixels::{Color, PixelFormat}, rect::Rect, render::{FPoint, ScaleMode, Texture}, surface::Surface};
 
-use crate::{MainContext, SubProgram, compute_shaders::PointU32, compute_snowball_search::{self, SnowballSearchParameters, SnowballSearchResult}, frame_images, program_common::{FrameTimer, ScreenSpace, draw_circle, fpoint_camera_transform, window_to_world_f32}, rng::{LinearPrecomputedRNG, LinearRNG, PrecomputedRNG, RNG}, server::MessageToSend, snowballs::{SNOWBALLS_ORIGIN_X, SNOWBALLS_ORIGIN_Y, SnowArea}, windowing::{focus_game_window, window_set_focusable}};
+use crate::{MainContext, SubProgram, compute_shaders::PointU32, compute_backend::SearchJob, compute_snowball_search::{self, SnowballScoredPosition, SnowballSearchMode, SnowballSearchParameters}, frame_images, program_common::{FrameTimer, ScreenSpace, draw_circle, draw_notice, fpoint_camera_transform, window_to_world_f32}, rng::{gm_round, LinearRNG, PrecomputedRNGWindow, RNG}, rng_cache, server::MessageToSend, snowballs::{SNOWBALLS_ORIGIN_X, SNOWBALLS_ORIGIN_Y, SNOWBALL_SIMULATION_MAX_RNG_VALUES, SnowArea}, windowing::{focus_game_window, window_set_focusable}};
 
 #[derive(Clone)]
 struct PlacedSnowball {
@@ -14,6 +13,64 @@ struct PlacedSnowball {
 const WORLD_WIDTH: u32 = 640;
 const WORLD_HEIGHT: u32 = 480;
 
+// Gets the step count from just before a matched pattern, optionally simulating the pattern itself for a visualization
+fn simulate_matched_position(position: u32, rngs: &[RNG], precomputed_rng: Option<&mut PrecomputedRNGWindow>, snow_areas: Option<&mut [SnowArea; 8]>) -> Result<u32, &'static str> {
+    if rngs.len() == 1 {
+        // Get RNG from single seed, at correct position (computing more of it if necessary)
+        let precomputed_rng = precomputed_rng.ok_or("RNG is still being precomputed")?;
+        let start_position = (position as usize).checked_sub(1).ok_or("No step count before the first searched position")?;
+        precomputed_rng.extend_to(start_position + 1 + SNOWBALL_SIMULATION_MAX_RNG_VALUES);
+        let mut lprng = precomputed_rng.linear(start_position)?;
+
+        // Use precomputed RNG for step count
+        let step_count = gm_round(lprng.random(30.0)) as u32;
+
+        // Use the same precomputed RNG to simulate the snowballs
+        if let Some(snow_areas) = snow_areas {
+            *snow_areas = SnowArea::new_array();
+            SnowArea::simulate_array(snow_areas, &mut lprng);
+        }
+        Ok(step_count)
+    } else {
+        // Get RNG from one of the seeds, at initial position (classic mode)
+        let mut rng = rngs[position as usize].clone();
+
+        // Use regular RNG for step count
+        _ = rng.next_u32();
+        let step_count = gm_round(rng.random(30.0)) as u32;
+
+        // Use the same regular RNG to simulate the snowballs
+        if let Some(snow_areas) = snow_areas {
+            *snow_areas = SnowArea::new_array();
+            SnowArea::simulate_array(snow_areas, &mut rng);
+        }
+        Ok(step_count)
+    }
+}
+
+// Creates status text for the number of patterns still matching the placed snowballs
+fn patterns_remaining_text(count: u32) -> String {
+    if count == 1 {
+        "1 pattern remains".to_owned()
+    } else {
+        format!("{} patterns remain", count)
+    }
+}
+
+// Creates menu instructions for the given step count
+fn create_instructions(step_count: u32) -> String {
+    let menu_buffer = step_count % 2 == 1;
+    let up_down_times = (step_count / 2) + (if menu_buffer { 1 } else { 0 });
+    match up_down_times {
+        0 => "NO UP/DOWN".to_owned(),
+        times => if menu_buffer {
+            format!("Menu buffer\nUp/down {} time{}", times, if times != 1 { "s" } else { "" })
+        } else {
+            format!("Up/down {} time{}", times, if times != 1 { "s" } else { "" })
+        }
+    }
+}
+
 pub fn run(main_context: &mut MainContext) -> SubProgram {
     // Visualization stuff
     let mut snow_areas = SnowArea::new_array();
@@ -32,15 +89,19 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
     let mut hovering_snowball: Option<PlacedSnowball> = None;
     let num_to_click = 4;
 
+    // Take the config as of entering (a reloaded config is picked up on next entry)
+    let config = Rc::clone(&main_context.config);
+
     // Initialize RNG
-    let runner_version = &main_context.config.runner_version;
+    let runner_version = &config.runner_version;
     let rngs: Arc<Vec<RNG>>;
     let rng_range: u32;
+    let mut rng_seed_and_start: Option<(u32, usize)> = None;
     if main_context.error_returning {
         main_context.error_returning = false;
 
         // Create precomputed RNGs for all seeds
-        let seeds = RNG::calculate_unique_seeds(runner_version.rng_15bit(), runner_version.rng_signed());
+        let seeds = rng_cache::load_unique_seeds(runner_version);
         let mut rngs_vec = Vec::with_capacity(seeds.len());
         for seed in seeds {
             rngs_vec.push(RNG::new(seed, runner_version.rng_15bit(), runner_version.rng_signed(), runner_version.rng_old_poly()));
@@ -60,8 +121,10 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
             Some(pos) => pos,
             None => panic!()
         };
+        let start_position = min_rng_position + main_context.config.snowball_search_start_offset as usize;
         let mut rng = RNG::new(rng_seed, runner_version.rng_15bit(), runner_version.rng_signed(), runner_version.rng_old_poly());
-        rng.skip(min_rng_position + main_context.config.snowball_search_start_offset as usize);
+        rng.skip(start_position);
+        rng_seed_and_start = Some((rng_seed, start_position));
 
         rng_range = main_context.config.snowball_search_range;
         rngs = Arc::new(vec![rng]);
@@ -69,41 +132,30 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
 
     // Initialize compute thread
     let rngs_thread = rngs.clone();
-    let compute_end_signal = Arc::new(AtomicBool::new(false));
-    let compute_end_signal_thread = compute_end_signal.clone();
-    let compute_perform_search_signal = Arc::new(AtomicBool::new(false));
-    let compute_perform_search_signal_thread = compute_perform_search_signal.clone();
     let compute_preload_completed_signal = Arc::new(AtomicBool::new(false));
     let compute_preload_completed_signal_thread = compute_preload_completed_signal.clone();
-    let compute_parameters = Arc::new(Mutex::new(SnowballSearchParameters {
+    let compute_precomputed_rng = Arc::new(Mutex::new(None as Option<PrecomputedRNGWindow>));
+    let compute_precomputed_rng_thread = compute_precomputed_rng.clone();
+    let compute_backend = main_context.config.compute_backend;
+    let runner_profile = *runner_version;
+    let mut compute_job = SearchJob::spawn(SnowballSearchParameters {
+        search_mode: SnowballSearchMode::Exact,
         search_range: 0,
+        max_candidates: 0,
         matching_snowballs: vec![]
-    }));
-    let compute_parameters_thread = compute_parameters.clone();
-    let compute_result = Arc::new(Mutex::new(SnowballSearchResult::new()));
-    let compute_result_thread = compute_result.clone();
-    let compute_precomputed_rng = Arc::new(Mutex::new(None as Option<PrecomputedRNG>));
-    let compute_precomputed_rng_thread = compute_precomputed_rng.clone();
-    let compute_join_handle = thread::spawn(move || {
+    }, move |job| {
         let rngs = Arc::clone(&rngs_thread);
 
         // If only one seed, precompute the RNG for it for fast visual display/lookup later
-        if rngs.len() == 1 {
+        if let Some((rng_seed, start_position)) = rng_seed_and_start {
             let mut precomputed_rng = compute_precomputed_rng_thread.lock().unwrap();
-            *precomputed_rng = Some(rngs[0].clone().precompute(rng_range as usize + 10_000));
+            *precomputed_rng = Some(rng_cache::load_precomputed_rng_window(&runner_profile, rng_seed, start_position, rng_range as usize + 10_000));
             drop(precomputed_rng);
         }
 
-        compute_snowball_search::thread_func(&rngs, rng_range as usize,
-            Arc::clone(&compute_end_signal_thread), Arc::clone(&compute_perform_search_signal_thread), 
-            Arc::clone(&compute_preload_completed_signal_thread), Arc::clone(&compute_parameters_thread), 
-            Arc::clone(&compute_result_thread));
+        compute_snowball_search::thread_func(&rngs, rng_range as usize, job,
+            Arc::clone(&compute_preload_completed_signal_thread), compute_backend);
     });
-    defer! {
-        // End compute thread
-        compute_end_signal.store(true, Ordering::Relaxed);
-        compute_join_handle.thread().unpark();
-    };
 
     // Set up for drawing
     let camera_position = FPoint::new(0.0, 580.0 - 240.0);
@@ -113,9 +165,12 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
     let circle_draw_offset = main_context.config.runner_version.circle_draw_offset();
     let x_limit = SNOWBALLS_ORIGIN_X + circle_draw_offset;
 
-    // State for whether a search is currently queued, or whether a search is currently in progress
+    // State for whether a search is currently queued (and whether a scored search is queued, after nothing matches).
+    // Searches narrow down the surviving patterns as each snowball is placed, so they're queued on every click/undo.
     let mut queued_search = false;
-    let mut waiting_for_search_result = false;
+    let mut queued_scored_search = false;
+    let mut current_search_mode = SnowballSearchMode::Narrowing;
+    let mut searched_snowball_count = 0;
 
     // Screenshot with snowballs to be displayed
     let mut screenshot_texture: Option<Texture> = None;
@@ -123,8 +178,17 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
     // Instructions, once found
     let mut instructions: Option<String> = None;
 
-    // Whether to show an error message due to no patterns matching at all
-    let mut no_patterns_matching = false;
+    // Error message to show when no patterns (or multiple disagreeing patterns) matched
+    let mut search_fail_text: Option<String> = None;
+
+    // Number of patterns remaining after the latest search, shown live while placing snowballs
+    let mut search_status_text: Option<String> = None;
+
+    // Closest patterns from a scored search, which can be used manually when nothing matched exactly
+    let mut closest_positions: Vec<SnowballScoredPosition> = Vec::new();
+
+    // Import any screenshots given on the command line
+    main_context.import_launch_screenshots();
 
     // Begin main loop
     let mut event_pump = main_context.sdl_context.event_pump().unwrap();
@@ -138,12 +202,15 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
             break;
         }
 
+        // Pick up any changes to the config
+        main_context.poll_config_reload();
+
         // Start frame
         let frame_timer = FrameTimer::start(30);
         let screen_space = ScreenSpace::new(&main_context);
 
         // Process events
-        for event in event_pump.poll_iter() {
+        for event in main_context.poll_events(&mut event_pump) {
             match event {
                 Event::Quit {..} => {
                     return SubProgram::None
@@ -151,6 +218,10 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
+                Event::DropBegin {..} | Event::DropFile {..} | Event::DropComplete {..} => {
+                    // Import screenshots from image files dropped onto the window
+                    main_context.handle_drop_event(&event);
+                },
                 
                 Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => {
                     let (world_x, world_y) = window_to_world_f32(x, y, actual_world_view, screen_space.rect());
@@ -160,10 +231,10 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                             x: f32::max(world_x, x_limit as f32),
                             y: world_y
                         });
-                        if placed_snowballs.len() >= num_to_click {
-                            // Enough snowballs have been clicked - queue a search
-                            queued_search = true;
-                        }
+                        closest_positions.clear();
+
+                        // Narrow down the remaining patterns
+                        queued_search = true;
                     } else {
                         // Start placing snowball
                         placing_snowball = Some(PlacedSnowball {
@@ -178,11 +249,10 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                         let (world_x, world_y) = window_to_world_f32(x, y, actual_world_view, screen_space.rect());
                         (placing_snowball.x, placing_snowball.y) = (f32::max(world_x, x_limit as f32), world_y);
                         placed_snowballs.push(placing_snowball.clone());
+                        closest_positions.clear();
 
-                        if placed_snowballs.len() >= num_to_click {
-                            // Enough snowballs have been clicked - queue a search
-                            queued_search = true;
-                        }
+                        // Narrow down the remaining patterns
+                        queued_search = true;
                     }
                     placing_snowball = None;
                 },
@@ -203,16 +273,48 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                 Event::MouseButtonDown { mouse_btn: MouseButton::Middle, .. } => {
                     // Clear placed snowballs
                     placed_snowballs.clear();
-                    no_patterns_matching = false;
+                    closest_positions.clear();
+                    queued_search = false;
+                    queued_scored_search = false;
+                    search_fail_text = None;
+                    search_status_text = None;
                 },
                 Event::MouseButtonDown { mouse_btn: MouseButton::Right, .. } => {
                     // Undo last snowball placed
                     if !placed_snowballs.is_empty() {
                         placed_snowballs.pop();
+                        closest_positions.clear();
+                        queued_scored_search = false;
 
                         // Get rid of text once too few to search
                         if placed_snowballs.len() < num_to_click {
-                            no_patterns_matching = false;
+                            search_fail_text = None;
+                        }
+
+                        // Expand the remaining patterns back out
+                        if placed_snowballs.is_empty() {
+                            queued_search = false;
+                            search_status_text = None;
+                        } else {
+                            queued_search = true;
+                        }
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::Return), .. } => {
+                    // Use the closest pattern, if nothing matched exactly
+                    if let Some(closest) = closest_positions.first() {
+                        println!("Using closest position {} (total error {}, max error {})", closest.position, closest.total_error, closest.max_error);
+                        let mut precomputed_rng = compute_precomputed_rng.lock().unwrap();
+                        let step_count = simulate_matched_position(closest.position, &rngs, precomputed_rng.as_mut(), Some(&mut snow_areas));
+                        drop(precomputed_rng);
+                        match step_count {
+                            Ok(step_count) => {
+                                show_visualization = true;
+                                instructions = Some(create_instructions(step_count));
+                                closest_positions.clear();
+                                search_fail_text = None;
+                            },
+                            Err(e) => search_fail_text = Some(format!("Failed to use closest pattern: {}", e))
                         }
                     }
                 }
@@ -221,8 +323,12 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         }
 
         // Handle queued searches
-        if queued_search && !waiting_for_search_result {
+        if (queued_search || queued_scored_search) && !compute_job.is_searching() {
+            // A new narrowing search makes any pending scored search obsolete
+            current_search_mode = if queued_search { SnowballSearchMode::Narrowing } else { SnowballSearchMode::Scored };
+            searched_snowball_count = placed_snowballs.len();
             queued_search = false;
+            queued_scored_search = false;
 
             // Create list of matching snowballs to pass to search
             let mut matching_snowballs: Vec<PointU32> = Vec::with_capacity(32);
@@ -233,70 +339,106 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
             }
 
             // Begin search
-            *compute_parameters.lock().unwrap() = SnowballSearchParameters {
+            compute_job.begin_search(SnowballSearchParameters {
+                search_mode: current_search_mode,
                 search_range: if rngs.len() == 1 { rng_range } else { rngs.len() as u32 },
+                max_candidates: main_context.config.search_max_candidates,
                 matching_snowballs
-            };
-            compute_perform_search_signal.store(true, Ordering::Relaxed);
-            compute_join_handle.thread().unpark();
-            waiting_for_search_result = true;
+            });
         }
 
         // Check for incoming snowball search results
-        if waiting_for_search_result && !compute_perform_search_signal.load(Ordering::Relaxed) {
-            waiting_for_search_result = false;
-
-            let search_result = compute_result.lock().unwrap();
-            if search_result.match_count == 1 {
-                // Singular match! Figure out past RNG...
-                println!("Matched position is {}", search_result.single_matched_position);
-
-                // Use precomputed RNG to get the step count from just before the match, and create visualization
-                let step_count: u32;
-                if rngs.len() == 1 {
-                    // Get RNG from single seed, at correct position
-                    let precomputed_rng = compute_precomputed_rng.lock().unwrap();
-                    let mut lprng = LinearPrecomputedRNG::new(precomputed_rng.as_ref().unwrap(), (search_result.single_matched_position - 1) as usize);
-
-                    // Use precomputed RNG for step count
-                    step_count = f64::round_ties_even(lprng.next_f64(30.0)) as u32;
-
-                    // Use the same precomputed RNG to simulate the snowballs for a visualization
-                    snow_areas = SnowArea::new_array();
-                    SnowArea::simulate_array(&mut snow_areas, &mut lprng);
-                } else {
-                    // Get RNG from one of the seeds, at initial position (classic mode)
-                    let mut rng = rngs[search_result.single_matched_position as usize].clone();
-
-                    // Use regular RNG for step count
-                    _ = rng.next_u32();
-                    step_count = f64::round_ties_even(rng.next_f64(30.0)) as u32;
-
-                    // Use the same regular RNG to simulate the snowballs for a visualization
-                    snow_areas = SnowArea::new_array();
-                    SnowArea::simulate_array(&mut snow_areas, &mut rng);
+        if let Some(search_result) = main_context.poll_search_result(&mut compute_job) {
+            if queued_search || placed_snowballs.is_empty() {
+                // Snowballs changed since this search began, so its results are outdated
+            } else if current_search_mode == SnowballSearchMode::Scored {
+                // Nothing matched exactly, so show the closest patterns instead
+                let mut precomputed_rng = compute_precomputed_rng.lock().unwrap();
+                let mut text = "No matching patterns found!".to_owned();
+                if !search_result.scored_positions.is_empty() {
+                    text += " Closest patterns:";
                 }
-                show_visualization = true;
-
-                // Create instructions
-                let menu_buffer = step_count % 2 == 1;
-                let up_down_times = (step_count / 2) + (if menu_buffer { 1 } else { 0 });
-                instructions = Some(match up_down_times {
-                    0 => "NO UP/DOWN".to_owned(),
-                    times => if menu_buffer {
-                        format!("Menu buffer\nUp/down {} time{}", times, if times != 1 { "s" } else { "" })
-                    } else {
-                        format!("Up/down {} time{}", times, if times != 1 { "s" } else { "" })
+                for scored in search_result.scored_positions.iter() {
+                    let step_count = match simulate_matched_position(scored.position, &rngs, precomputed_rng.as_mut(), None) {
+                        Ok(step_count) => step_count.to_string(),
+                        Err(_) => "?".to_owned()
+                    };
+                    text += &format!("\nPattern at {}: {} steps, total error {}, off by up to {:.1}px",
+                        scored.position, step_count, scored.total_error, f64::sqrt(scored.max_error as f64));
+
+                    // Anything further than the exact match threshold is likely a misplaced snowball
+                    if scored.max_error > 4 {
+                        text += &format!(" (snowball #{} likely misplaced)", scored.outlier_index + 1);
                     }
-                });
-
-                no_patterns_matching = false;
+                }
+                drop(precomputed_rng);
+                if !search_result.scored_positions.is_empty() {
+                    text += "\n[Enter] - Use closest pattern";
+                }
+                search_fail_text = Some(text);
+                closest_positions = search_result.scored_positions.clone();
+            } else if searched_snowball_count < num_to_click && search_result.match_count > 0 {
+                // Not enough snowballs placed to be sure of the pattern yet, so just show what remains
+                search_status_text = Some(patterns_remaining_text(search_result.match_count));
+                search_fail_text = None;
             } else {
-                println!("Match count = {}, data = {}", search_result.match_count, search_result.single_matched_position);
-
-                // Display extra message if no patterns matched at all...
-                if search_result.match_count == 0 {
-                    no_patterns_matching = true;
+                search_status_text = Some(patterns_remaining_text(search_result.match_count));
+
+                // Figure out the step count from just before each matched pattern
+                let mut precomputed_rng = compute_precomputed_rng.lock().unwrap();
+                let candidate_step_counts: Result<Vec<u32>, &'static str> = search_result.matched_positions.iter()
+                    .map(|&position| simulate_matched_position(position, &rngs, precomputed_rng.as_mut(), None))
+                    .collect();
+                drop(precomputed_rng);
+
+                match candidate_step_counts {
+                    Err(e) => {
+                        println!("Failed to simulate matched patterns: {}", e);
+                        search_fail_text = Some(format!("Failed to simulate matched patterns: {}", e));
+                    },
+                    Ok(candidate_step_counts) => {
+                        // Usable as long as every match was returned, and all of them agree on the same step count
+                        let all_candidates_agree = search_result.match_count >= 1 &&
+                            search_result.match_count as usize == candidate_step_counts.len() &&
+                            candidate_step_counts.iter().all(|&step_count| step_count == candidate_step_counts[0]);
+                        if all_candidates_agree {
+                            // Match (or all matches) found! Figure out past RNG...
+                            let matched_position = search_result.matched_positions[0];
+                            println!("Matched position is {}", matched_position);
+                            if search_result.match_count > 1 {
+                                println!("All {} matched positions agree on the same step count", search_result.match_count);
+                            }
+
+                            // Simulate the match again to create the visualization (its step count was found above)
+                            let mut precomputed_rng = compute_precomputed_rng.lock().unwrap();
+                            _ = simulate_matched_position(matched_position, &rngs, precomputed_rng.as_mut(), Some(&mut snow_areas));
+                            drop(precomputed_rng);
+                            show_visualization = true;
+
+                            // Create instructions
+                            instructions = Some(create_instructions(candidate_step_counts[0]));
+
+                            search_fail_text = None;
+                        } else {
+                            println!("Match count = {}, positions = {:?}", search_result.match_count, search_result.matched_positions);
+
+                            // Display extra message if no patterns matched at all, or if the matches disagree
+                            if search_result.match_count == 0 {
+                                // Look for the closest patterns instead
+                                search_fail_text = Some("No matching patterns found! Looking for closest patterns...".to_owned());
+                                queued_scored_search = true;
+                            } else {
+                                let mut text = format!("{} matching patterns disagree:", search_result.match_count);
+                                for (position, step_count) in search_result.matched_positions.iter().zip(candidate_step_counts.iter()) {
+                                    text += &format!("\nPattern at {}: {} steps", position, step_count);
+                                }
+                                if search_result.match_count as usize > search_result.matched_positions.len() {
+                                    text += &format!("\n(and {} more)", search_result.match_count as usize - search_result.matched_positions.len());
+                                }
+                                search_fail_text = Some(text);
+                            }
+                        }
+                    }
                 }
             }
         }
@@ -374,15 +516,27 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
             Color::RGBA(0, 0, 0, 128),
             16.0);
 
-        // Draw text if no patterns matched at all
-        if no_patterns_matching {
+        // Draw number of patterns remaining
+        if let Some(search_status_text) = &search_status_text {
             _ = main_context.font.draw_text(
                 main_context, 
-                "No matching patterns found!", 
+                search_status_text, 
                 screen_space.x_world_to_screen(8.0), screen_space.y_world_to_screen(WORLD_HEIGHT as f32 - 32.0),
                 0.0, 1.0,
                 0, 
                 screen_space.scale(), 
+                Color::RGB(255, 255, 255));
+        }
+
+        // Draw text if no patterns matched at all, or the matches disagree
+        if let Some(search_fail_text) = &search_fail_text {
+            _ = main_context.font.draw_text(
+                main_context, 
+                search_fail_text, 
+                screen_space.x_world_to_screen(8.0), screen_space.y_world_to_screen(WORLD_HEIGHT as f32 - 56.0),
+                0.0, 1.0,
+                0, 
+                screen_space.scale(), 
                 Color::RGB(255, 0, 0));
         }
         
@@ -407,6 +561,9 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                 Color::RGB(128, 128, 128));
         }
 
+        // Draw notice about config reloading, if any
+        _ = draw_notice(main_context, &screen_space);
+
         // Present latest canvas
         main_context.canvas.present();
 
@@ -446,8 +603,12 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         if local_screenshot_data.len() >= 1 {
             // Hide old stuff
             placed_snowballs.clear();
+            closest_positions.clear();
+            queued_search = false;
+            queued_scored_search = false;
             show_visualization = false;
-            no_patterns_matching = false;
+            search_fail_text = None;
+            search_status_text = None;
 
             // Get screenshot data
             let screenshot_data = &local_screenshot_data.pop().unwrap();
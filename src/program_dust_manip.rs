@@ -1,8 +1,8 @@
 extern crate sdl3;
 
-use compute_dust_search::{DustSearchMode, DustSearchParameters, DustSearchResult};
+use compute_backend::SearchJob;
+use compute_dust_search::{DustSearchMode, DustSearchParameters};
 use compute_shaders::PointU32;
-use defer_rs::defer;
 use encounter_data::{Battlegroup, Encounterer};
 use image::ImageReader;
 use manip_data::MANIP_SETUPS_CORE;
@@ -17,18 +17,17 @@ use sdl3::event::Event;
 use sdl3::keyboard::Keycode;
 use sdl3_ttf_sys::ttf::{TTF_HORIZONTAL_ALIGN_LEFT, TTF_HORIZONTAL_ALIGN_RIGHT};
 use server::ScreenshotData;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
-use rng::RNG;
 use dust::{DustAnimation, DustSearchConfig};
 
 use crate::windowing::window_set_focusable;
 use crate::program_common::{rect_from_texture, rect_to_frect, window_to_world, FrameTimer, ScreenSpace};
-use crate::rng::LinearRNG;
+use crate::rng::RNG;
 use crate::server::MessageToSend;
-use crate::{compute_dust_search, compute_shaders, dust, encounter_data, frame_images, manip_data, windowing, program_common, rng, server, util, MainContext, SubProgram};
+use crate::{compute_backend, compute_dust_search, compute_shaders, dust, encounter_data, frame_images, manip_data, windowing, program_common, rng_cache, server, util, MainContext, SubProgram};
 
 #[derive(Clone)]
 struct PlacedDustParticle {
@@ -120,6 +119,13 @@ fn set_new_search_config(main_context: &MainContext, context: &mut DustManipCont
     context.update_screenshot_delay_time(main_context);
 }
 
+// Finds the first manip setup that leads to the target battlegroup, given the RNG position predicted for after the battle
+// (relative to the given RNG, which is jumped ahead to it rather than precomputing everything in between)
+fn find_manip_setup(encounterer: Encounterer, rng: &RNG, predicted_pos: usize, target_battlegroup: Battlegroup) -> Option<usize> {
+    let setup_rng = rng.at(predicted_pos + 3);
+    MANIP_SETUPS_CORE.iter().position(|setup| encounterer.get_battlegroup_at_pos(&setup_rng, setup.rng_amount) == target_battlegroup)
+}
+
 const WORLD_WIDTH: u32 = 640;
 const WORLD_HEIGHT: u32 = 480;
 
@@ -144,8 +150,11 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
     let mut search_anim: Option<DustAnimation> = None;
     let mut debug_anim: Option<DustAnimation> = None;
 
+    // Take the config as of entering (a reloaded config is picked up on next entry)
+    let config = Rc::clone(&main_context.config);
+
     // Initialize RNG
-    let runner_version = &main_context.config.runner_version;
+    let runner_version = &config.runner_version;
     let rng_seed = match main_context.run_context.rng_seed() {
         Some(seed) => seed,
         None => {
@@ -157,41 +166,28 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         Some(pos) => pos,
         None => panic!()
     };
-    let mut rng = RNG::new(rng_seed, runner_version.rng_15bit(), runner_version.rng_signed(), runner_version.rng_old_poly());
-    rng.skip(min_rng_position);
+    let num_to_compute = compute_dust_search::DUST_SEARCH_RNG_COUNT;
+    let prng = Arc::new(rng_cache::load_precomputed_rng(runner_version, rng_seed, min_rng_position, num_to_compute));
 
-    let num_to_compute = 500_000;
-    let prng = Arc::new(rng.precompute(num_to_compute));
+    // Encounters are predicted by jumping ahead from here, which can go beyond the search range on long runs
+    let min_position_rng = RNG::new(rng_seed, runner_version.rng_15bit(), runner_version.rng_signed(), runner_version.rng_old_poly()).at(min_rng_position);
 
     // Initialize compute thread
-    let compute_end_signal = Arc::new(AtomicBool::new(false));
-    let compute_end_signal_thread = compute_end_signal.clone();
-    let compute_perform_search_signal = Arc::new(AtomicBool::new(false));
-    let compute_perform_search_signal_thread = compute_perform_search_signal.clone();
     let prng_thread = prng.clone();
-    let compute_parameters = Arc::new(Mutex::new(DustSearchParameters {
+    let compute_backend = main_context.config.compute_backend;
+    let mut compute_job = SearchJob::spawn(DustSearchParameters {
         search_mode: DustSearchMode::LastFrame,
         search_range: 0,
+        max_candidates: 0,
         last_frame_rng_offset: 0,
         last_frame_particle_count: 0,
         second_last_frame_particle_count: 0,
         initial_rng_skip_amount: 0,
         matching_particles: vec![],
         initial_particles: vec![],
-    }));
-    let compute_parameters_thread = compute_parameters.clone();
-    let compute_result = Arc::new(Mutex::new(DustSearchResult { match_count: 0, single_matched_position: 0 }));
-    let compute_result_thread = compute_result.clone();
-    let compute_join_handle = thread::spawn(move || {
-        compute_dust_search::thread_func(
-            Arc::clone(&compute_end_signal_thread), Arc::clone(&compute_perform_search_signal_thread), 
-            Arc::clone(&prng_thread), Arc::clone(&compute_parameters_thread), Arc::clone(&compute_result_thread));
+    }, move |job| {
+        compute_dust_search::thread_func(job, prng_thread, compute_backend);
     });
-    defer! {
-        // End compute thread
-        compute_end_signal.store(true, Ordering::Relaxed);
-        compute_join_handle.thread().unpark();
-    };
 
     // Initialize screenshot structures
     let mut screenshots: Vec<ScreenshotData> = Vec::with_capacity(32);
@@ -203,9 +199,8 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
     let mut placing_particle: Option<PlacedDustParticle> = None;
     let mut hovering_particle: Option<PlacedDustParticle> = None;
 
-    // State for whether a search is currently queued, or whether a search is currently in progress
+    // State for whether a search is currently queued
     let mut queued_search = false;
-    let mut waiting_for_search_result = false;
 
     // Initialize frame pairs, loading the template image from the assets
     let mut dust_search_frame_pairs: Vec<DustFramePair> = Vec::with_capacity(100);
@@ -220,6 +215,9 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
     // String to display for instructions
     let mut dust_manip_string: Option<String> = None;
 
+    // String to display when the latest search failed to find a usable position
+    let mut dust_search_fail_string: Option<String> = None;
+
     // Times (in milliseconds) for raising this program window, and for preventing focusing of this window, respectively
     let mut raise_window_time: Option<u64> = None;
     let mut focus_window_buffer_time: Option<u64> = None;
@@ -233,6 +231,9 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
     // Make sure plugin takes more than one screenshot... (the delay is managed by the manip context)
     _ = main_context.message_to_send_sender.send(MessageToSend::new_screenshot_mode(false));
 
+    // Import any screenshots given on the command line
+    main_context.import_launch_screenshots();
+
     // Start main loop
     let mut world_texture = main_context.texture_creator
         .create_texture_target(main_context.texture_creator.default_pixel_format(), WORLD_WIDTH, WORLD_HEIGHT)
@@ -244,6 +245,9 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         if main_context.panic_occurred.load(Ordering::Relaxed) {
             break;
         }
+
+        // Pick up any changes to the config
+        main_context.poll_config_reload();
         
         let frame_timer = FrameTimer::start(30);
         let screen_space = ScreenSpace::new(&main_context);
@@ -251,7 +255,7 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         main_context.canvas.set_draw_color(Color::RGB(0, 0, 0));
         main_context.canvas.set_blend_mode(sdl3::render::BlendMode::Blend);
         main_context.canvas.clear();
-        for event in event_pump.poll_iter() {
+        for event in main_context.poll_events(&mut event_pump) {
             match event {
                 Event::Quit {..} => {
                     return SubProgram::None
@@ -259,6 +263,10 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
+                Event::DropBegin {..} | Event::DropFile {..} | Event::DropComplete {..} => {
+                    // Import screenshots from image files dropped onto the window
+                    main_context.handle_drop_event(&event);
+                },
                 Event::KeyDown { keycode: Some(Keycode::Space), .. } => {
                     let mut new_debug_anim = context.search_config.dust_data.create_animation();
                     let frame_end_offset: usize = match context.search_mode {
@@ -419,20 +427,31 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
 
         // Check for incoming dust search results
         if let Some(search_anim) = &search_anim {
-            if waiting_for_search_result && !compute_perform_search_signal.load(Ordering::Relaxed) {
-                waiting_for_search_result = false;
-
-                let search_result = compute_result.lock().unwrap();
-                if search_result.match_count == 1 {
-                    // Singular match! Predict future RNG...
-                    let predicted_pos = search_result.single_matched_position as usize + search_anim.get_after_battle_rng_calls(match leveled_up {
-                        true => context.search_config.text_length_lvup,
-                        false => context.search_config.text_length
-                    });
-                    if battlegroup_order_pos < battlegroup_order.len() - 1 {
-                        battlegroup_order_pos += 1;
+            if let Some(search_result) = main_context.poll_search_result(&mut compute_job) {
+                // Predict future RNG for every candidate position, and figure out which setup each of them needs
+                let text_length = match leveled_up {
+                    true => context.search_config.text_length_lvup,
+                    false => context.search_config.text_length
+                };
+                let next_battlegroup_order_pos = usize::min(battlegroup_order_pos + 1, battlegroup_order.len() - 1);
+                let candidate_setups: Vec<Option<usize>> = search_result.matched_positions.iter().map(|&position| {
+                    let predicted_pos = position as usize + search_anim.get_after_battle_rng_calls(text_length);
+                    find_manip_setup(curr_encounterer, &min_position_rng, predicted_pos, battlegroup_order[next_battlegroup_order_pos])
+                }).collect();
+
+                // Usable as long as every match was returned, and all of them agree on the same setup
+                let all_candidates_agree = search_result.match_count >= 1 &&
+                    search_result.match_count as usize == candidate_setups.len() &&
+                    candidate_setups.iter().all(|setup| *setup == candidate_setups[0]);
+                if all_candidates_agree {
+                    // Match (or all matches) found! Predict future RNG...
+                    let matched_position = search_result.matched_positions[0];
+                    let predicted_pos = matched_position as usize + search_anim.get_after_battle_rng_calls(text_length);
+                    battlegroup_order_pos = next_battlegroup_order_pos;
+                    println!("Matched position is {}", matched_position);
+                    if search_result.match_count > 1 {
+                        println!("All {} matched positions agree on the same setup", search_result.match_count);
                     }
-                    println!("Matched position is {}", search_result.single_matched_position);
                     println!("Predicted position is {}", predicted_pos);
                     //for i in 0..4 {
                     //    println!("RNG value {} is {}", i, prng.get_f64(100.0, predicted_pos + i));
@@ -452,15 +471,13 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                     }
                     dust_manip_string = Some(str);
                     */
-                    for setup in MANIP_SETUPS_CORE {
-                        let bg = curr_encounterer.get_battlegroup_at_pos(&prng, predicted_pos + 3 + setup.rng_amount);
-                        if bg == battlegroup_order[battlegroup_order_pos] {
-                            println!("Setup targets position {}", predicted_pos + setup.rng_amount);
-                            str = setup.text.to_owned();
-                            break;
-                        }
+                    if let Some(setup_index) = candidate_setups[0] {
+                        let setup = &MANIP_SETUPS_CORE[setup_index];
+                        println!("Setup targets position {}", predicted_pos + setup.rng_amount);
+                        str = setup.text.to_owned();
                     }
                     dust_manip_string = Some(str);
+                    dust_search_fail_string = None;
 
                     // Make this program window unfocusable (for a fixed amount of buffer time), and focus the game window if possible
                     if window_set_focusable(main_context.canvas.window_mut(), false) {
@@ -476,7 +493,25 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                     set_new_search_config(main_context, &mut context, curr_battlegroup.get_dust_config());
                     selected_screenshot_texture = None;
                 } else {
-                    println!("Match count = {}, data = {}", search_result.match_count, search_result.single_matched_position);
+                    println!("Match count = {}, positions = {:?}", search_result.match_count, search_result.matched_positions);
+
+                    // Show what each candidate would need, so the disagreement is visible
+                    let mut str = if search_result.match_count == 0 {
+                        "Dust search failed: no positions matched".to_owned()
+                    } else {
+                        format!("Dust search failed: {} matched positions disagree", search_result.match_count)
+                    };
+                    for (position, setup) in search_result.matched_positions.iter().zip(candidate_setups.iter()) {
+                        let setup_text = match setup {
+                            Some(setup_index) => MANIP_SETUPS_CORE[*setup_index].text.replace('\n', " + "),
+                            None => "(unlucky, no good setup)".to_owned()
+                        };
+                        str += &format!("\n{}: {}", position, setup_text);
+                    }
+                    if search_result.match_count as usize > search_result.matched_positions.len() {
+                        str += &format!("\n(and {} more)", search_result.match_count as usize - search_result.matched_positions.len());
+                    }
+                    dust_search_fail_string = Some(str);
                 }
             }
         }
@@ -538,30 +573,12 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         }
 
         // Perform search if queued
-        if queued_search && !waiting_for_search_result {
+        if queued_search && !compute_job.is_searching() {
             queued_search = false;
 
-            let mut initial_particles: Vec<PointU32> = Vec::with_capacity(32);
-            let mut initial_particles_last_frame_count = 0;
-            let mut initial_particles_second_last_frame_count = 0;
-
-            let mut new_search_anim = context.search_config.dust_data.create_animation();
-            new_search_anim.compute_frame_rng_offsets();
-            for particle in new_search_anim.get_frames().last().unwrap().iter() {
-                initial_particles.push(PointU32::new(particle.get_x() as i16 - (num_attacks as i16), particle.get_y() as i16));
-                initial_particles_last_frame_count += 1;
-            }
-            match context.search_mode {
-                DustSearchMode::SecondToLastFrame | DustSearchMode::SecondToLastFrameEarly => {
-                    for particle in new_search_anim.get_frames().get(new_search_anim.get_frame_count() - 2).unwrap().iter() {
-                        initial_particles.push(PointU32::new(particle.get_x() as i16 - (num_attacks as i16), particle.get_y() as i16));
-                        initial_particles_second_last_frame_count += 1;
-                    }
-                }
-                DustSearchMode::LastFrame | DustSearchMode::LastFrameEarly => {}
-            }
-
             let matching_particles: Vec<PointU32> = placed_particles.iter().map(|p| PointU32::new(p.x as i16, p.y as i16)).collect();
+            let (search_parameters, new_search_anim) = compute_dust_search::create_search_parameters(&context.search_config, context.search_mode, num_attacks,
+                matching_particles, num_to_compute, main_context.config.search_max_candidates);
 
             /*
             if let Some(debug_anim) = &debug_anim {
@@ -588,35 +605,10 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
             }
             */
 
-            let frame_end_offset: usize = match context.search_mode {
-                DustSearchMode::LastFrame | DustSearchMode::LastFrameEarly => 1,
-                DustSearchMode::SecondToLastFrame | DustSearchMode::SecondToLastFrameEarly => 2
-            };
-            let initial_rng_skip_amount: u32 = 2 * (new_search_anim.get_frame_count() - frame_end_offset) as u32;
-
-            if frame_end_offset == 2 {
-                //println!("RNG skip amount between last two frames = {}", initial_rng_skip_amount);
-                //println!("expected second to last RNG frame offset = {}", new_search_anim.get_frame_rng_offset(new_search_anim.get_frame_count() - 2));
-                //println!("actual second to last RNG frame offset = {}", new_search_anim.get_frame_rng_offset(new_search_anim.get_frame_count() - 1) as u32 - ((initial_particles_second_last_frame_count * 2) + initial_rng_skip_amount));
-                assert!(new_search_anim.get_frame_rng_offset(new_search_anim.get_frame_count() - 1) as u32 - ((initial_particles_second_last_frame_count * 2) + initial_rng_skip_amount) ==
-                        new_search_anim.get_frame_rng_offset(new_search_anim.get_frame_count() - 2) as u32);
-            }
-
-            *compute_parameters.lock().unwrap() = DustSearchParameters {
-                search_range: (num_to_compute - new_search_anim.get_total_rng_calls() - 10_000) as u32,
-                last_frame_rng_offset: new_search_anim.get_frame_rng_offset(new_search_anim.get_frame_count() - 1) as u32,
-                matching_particles,
-                initial_particles: initial_particles.clone(),
-                last_frame_particle_count: initial_particles_last_frame_count,
-                second_last_frame_particle_count: initial_particles_second_last_frame_count,
-                initial_rng_skip_amount,
-                search_mode: context.search_mode
-            };
+            dust_search_fail_string = None;
+            compute_job.begin_search(search_parameters);
 
             search_anim = Some(new_search_anim);
-            waiting_for_search_result = true;
-            compute_perform_search_signal.store(true, Ordering::Relaxed);
-            compute_join_handle.thread().unpark();
         }
 
         // Check for incoming screenshots from the server
@@ -624,6 +616,7 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         if local_screenshot_data.len() > 1 {
             // Switch to the selecting frame state
             dust_manip_state = DustManipState::SelectingFrame;
+            dust_search_fail_string = None;
             selected_screenshot = 0;
             placed_particles.clear();
 
@@ -817,6 +810,21 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                 Color::RGB(128, 128, 128));
         }
 
+        // Draw text if the latest search failed
+        if let Some(fail_text) = &dust_search_fail_string {
+            _ = main_context.font.draw_text(
+                main_context, 
+                fail_text, 
+                screen_space.x_world_to_screen(16.0), screen_space.y_world_to_screen(WORLD_HEIGHT as f32 - 16.0),
+                0.0, 1.0,
+                0, 
+                screen_space.scale(), 
+                Color::RGB(255, 0, 0));
+        }
+
+        // Draw notice about config reloading, if any
+        _ = program_common::draw_notice(main_context, &screen_space);
+
         // Present latest canvas
         main_context.canvas.present();
 
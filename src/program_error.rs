@@ -1,8 +1,11 @@
 use std::sync::atomic::Ordering;
 
-use sdl3::{event::Event, keyboard::Keycode, pixels::Color, rect::Rect, render::{ScaleMode, Texture}};
+use sdl3::{event::Event, keyboard::Keycode, pixels::Color, rect::Rect, render::{Canvas, ScaleMode, Texture, TextureCreator}, video::{Window, WindowContext}, Sdl};
 
-use crate::{program_common::{rect_from_texture, FrameTimer, ScreenSpace}, MainContext, SubProgram};
+use crate::{config::ConfigProblem, program_common::{draw_notice, rect_from_texture, FrameTimer, ScreenSpace}, text_rendering::Font, MainContext, SubProgram};
+
+// Most config problems that fit on screen at once (the rest are only in the console output)
+const MAX_CONFIG_PROBLEMS_SHOWN: usize = 6;
 
 pub fn run(main_context: &mut MainContext) -> SubProgram {
     // Render text for error message
@@ -19,6 +22,9 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         if main_context.panic_occurred.load(Ordering::Relaxed) {
             break;
         }
+
+        // Pick up any changes to the config
+        main_context.poll_config_reload();
         
         let frame_timer = FrameTimer::start(30);
         let screen_space = ScreenSpace::new(&main_context);
@@ -26,7 +32,7 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         main_context.canvas.set_draw_color(Color::RGB(0, 0, 0));
         main_context.canvas.set_blend_mode(sdl3::render::BlendMode::Blend);
         main_context.canvas.clear();
-        for event in event_pump.poll_iter() {
+        for event in main_context.poll_events(&mut event_pump) {
             match event {
                 Event::Quit {..} => {
                     return SubProgram::None;
@@ -49,6 +55,9 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         // Draw the error message
         _ = main_context.canvas.copy(&texture, texture_src_rect, screen_space.rect_world_to_screen(texture_dst_rect));
 
+        // Draw notice about config reloading, if any
+        _ = draw_notice(main_context, &screen_space);
+
         // Present latest canvas
         main_context.canvas.present();
 
@@ -65,4 +74,53 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
 
     main_context.error_return_to = SubProgram::None;
     if main_context.config.auto_return_to_naming_search { SubProgram::NamingSeedSearch } else { SubProgram::ProgramSelector }
-}
\ No newline at end of file
+}
+// Startup screen for when the config can't be loaded, which runs before anything else (so it can't rely on the main context).
+// Returns true if the config should be loaded again, or false to quit.
+pub fn run_config_problems(sdl_context: &Sdl, font: &Font, canvas: &mut Canvas<Window>, texture_creator: &TextureCreator<WindowContext>, problems: &[ConfigProblem]) -> bool {
+    // Render text listing the problems
+    let mut text = String::from("Error: config.json has problems that need to be fixed.\n");
+    for problem in problems.iter().take(MAX_CONFIG_PROBLEMS_SHOWN) {
+        text += &format!("\n- {}", problem);
+    }
+    if problems.len() > MAX_CONFIG_PROBLEMS_SHOWN {
+        text += &format!("\n- ...and {} more (see console output).", problems.len() - MAX_CONFIG_PROBLEMS_SHOWN);
+    }
+    text += "\n\nAfter fixing config.json, press Enter to load it again, or Esc to quit.";
+    let surface = font.render_text_autowrap(&text, Color::RGB(255, 255, 255), 600).expect("Failed to render text to surface");
+    let mut texture = Texture::from_surface(&surface, texture_creator).expect("Failed to create texture from surface");
+    drop(surface);
+    texture.set_scale_mode(ScaleMode::Nearest);
+    let texture_src_rect = rect_from_texture(&texture);
+    let texture_dst_rect = Rect::new(20, 20, texture_src_rect.w as u32, texture_src_rect.h as u32);
+
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    loop {
+        let frame_timer = FrameTimer::start(30);
+        let screen_space = ScreenSpace::from_canvas(canvas);
+
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.set_blend_mode(sdl3::render::BlendMode::Blend);
+        canvas.clear();
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    return false;
+                },
+                Event::KeyDown { keycode: Some(Keycode::Return), .. } => {
+                    return true;
+                },
+                _ => {}
+            }
+        }
+
+        // Draw the problem list
+        _ = canvas.copy(&texture, texture_src_rect, screen_space.rect_world_to_screen(texture_dst_rect));
+
+        // Present latest canvas
+        canvas.present();
+        
+        // Sleep until next frame
+        frame_timer.end_and_sleep();
+    }
+}
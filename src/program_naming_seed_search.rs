@@ -1,9 +1,8 @@
-use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread};
+use std::{rc::Rc, sync::{atomic::Ordering, Arc, Mutex}};
 
-use defer_rs::defer;
 use sdl3::{event::Event, keyboard::Keycode, mouse::MouseButton, pixels::{Color, PixelFormat}, rect::{Point, Rect}, render::{BlendMode, ScaleMode, Texture}, surface::Surface};
 
-use crate::{compute_naming_search::{self, NamingSearchParameters, NamingSearchResult}, program_common::{self, window_to_world, FrameTimer, ScreenSpace}, rng::RNG, server::MessageToSend, windowing::{focus_game_window, window_set_focusable}, MainContext, SubProgram};
+use crate::{compute_backend::SearchJob, compute_naming_search::{self, NamingSearchCandidate, NamingSearchParameters}, program_common::{self, window_to_world, FrameTimer, ScreenSpace}, rng_cache, seed_timing, server::MessageToSend, windowing::{focus_game_window, window_set_focusable}, MainContext, SubProgram};
 
 // Points on screen, such that if they aren't black, represents a random(0.5) that is definitely > 0.25
 // Ordered by letters A-Z then a-z, with Y offset coming first due to reverse order argument evaluation.
@@ -89,11 +88,14 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
             selected: false
         });
     }
+
+    // Take the config as of entering (a reloaded config is picked up on next entry)
+    let config = Rc::clone(&main_context.config);
     let mut naming_rect_index = 0;
-    let mut naming_rect = &main_context.config.naming_rects[naming_rect_index];
+    let mut naming_rect = &config.naming_rects[naming_rect_index];
     let mut naming_rect_zoom = Rect::new(naming_rect.zoom.x as i32, naming_rect.zoom.y as i32, naming_rect.zoom.w, naming_rect.zoom.h);
     let mut naming_rect_crop = Rect::new(naming_rect.crop.x as i32, naming_rect.crop.y as i32, naming_rect.crop.w, naming_rect.crop.h);
-    let naming_rect_count = main_context.config.naming_rects.len();
+    let naming_rect_count = config.naming_rects.len();
 
     // Last server connected state
     let mut last_server_connected = main_context.server_connected.load(Ordering::Relaxed);
@@ -103,43 +105,37 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
     _ = main_context.message_to_send_sender.send(MessageToSend::new_screenshot_start_delay(0));
 
     // Initialize compute thread
-    let compute_end_signal = Arc::new(AtomicBool::new(false));
-    let compute_end_signal_thread = compute_end_signal.clone();
-    let compute_perform_search_signal = Arc::new(AtomicBool::new(false));
-    let compute_perform_search_signal_thread = compute_perform_search_signal.clone();
-    let runner_version = &main_context.config.runner_version;
-    let unique_seeds = Arc::new(Mutex::new(RNG::calculate_unique_seeds(runner_version.rng_15bit(), runner_version.rng_signed())));
+    let runner_version = &config.runner_version;
+    let unique_seeds = Arc::new(Mutex::new(rng_cache::load_unique_seeds(runner_version)));
     let unique_seeds_thread = unique_seeds.clone();
-    let compute_parameters = Arc::new(Mutex::new(NamingSearchParameters {
+    let compute_backend = main_context.config.compute_backend;
+    let mut compute_job = SearchJob::spawn(NamingSearchParameters {
         search_range: 0,
+        max_candidates: 0,
         matching_pixels: vec![],
         rng_15bit: false,
         rng_old_poly: false,
-        rng_signed: false
-    }));
-    let compute_parameters_thread = compute_parameters.clone();
-    let compute_result = Arc::new(Mutex::new(NamingSearchResult { match_count: 0, single_matched_seed: 0, single_matched_position: 0 }));
-    let compute_result_thread = compute_result.clone();
-    let compute_join_handle = thread::spawn(move || {
-        compute_naming_search::thread_func(
-            Arc::clone(&compute_end_signal_thread), Arc::clone(&compute_perform_search_signal_thread), 
-            Arc::clone(&unique_seeds_thread), Arc::clone(&compute_parameters_thread), Arc::clone(&compute_result_thread));
+        rng_signed: false,
+        seed_subset: None
+    }, move |job| {
+        compute_naming_search::thread_func(job, unique_seeds_thread, compute_backend);
     });
-    defer! {
-        // End compute thread
-        compute_end_signal.store(true, Ordering::Relaxed);
-        compute_join_handle.thread().unpark();
-    };
 
-    // State for whether a search is currently queued, or whether a search is currently in progress
+    // State for whether a search is currently queued
     let mut queued_search = false;
-    let mut waiting_for_search_result = false;
+
+    // Seeds near the captured launch time, if any, which get searched before all other seeds (along with the expected seed and tolerance they were chosen with)
+    let mut prioritized_seeds: Option<Arc<Vec<u32>>> = None;
+    let mut prioritized_seeds_built_with: (u32, u32) = (0, 0);
+    let mut searching_prioritized_seeds = false;
+    let mut queued_full_search = false;
 
     // Whether RNG was just found by this tool or not
     let mut rng_just_found = false;
 
-    // Match count for when a search fails
+    // Match count for when a search fails, along with any seeds/positions that did match
     let mut rng_fail_match_count = -1;
+    let mut rng_fail_candidates: Vec<NamingSearchCandidate> = Vec::new();
 
     // Countdown for automatically advancing, if enabled
     let mut auto_advance_countdown = 0;
@@ -147,6 +143,9 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
     // State for mouse dragging
     let mut last_selected_toggle_result = true;
 
+    // Import any screenshots given on the command line
+    main_context.import_launch_screenshots();
+
     // Screenshot for naming screen to be displayed
     let mut screenshot_texture: Option<Texture> = None;
     
@@ -164,6 +163,9 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
             break;
         }
 
+        // Pick up any changes to the config
+        main_context.poll_config_reload();
+
         // Advance to next tool if wanted
         if auto_advance_countdown > 0 {
             auto_advance_countdown -= 1;
@@ -182,7 +184,7 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         main_context.canvas.set_draw_color(Color::RGB(0, 0, 0));
         main_context.canvas.set_blend_mode(sdl3::render::BlendMode::Blend);
         main_context.canvas.clear();
-        for event in event_pump.poll_iter() {
+        for event in main_context.poll_events(&mut event_pump) {
             match event {
                 Event::Quit {..} => {
                     return SubProgram::None
@@ -190,6 +192,18 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
+                Event::DropBegin {..} | Event::DropFile {..} | Event::DropComplete {..} => {
+                    // Import screenshots from image files dropped onto the window
+                    main_context.handle_drop_event(&event);
+                },
+                Event::KeyDown { keycode: Some(Keycode::T), .. } => {
+                    // Capture the time the game is being launched at, for narrowing down seeds
+                    if let Some(clock) = main_context.read_seed_clock() {
+                        main_context.run_context.set_launch_seed_clock(clock);
+                        prioritized_seeds = None;
+                        println!("Captured launch seed clock {}", clock);
+                    }
+                },
                 Event::MouseMotion { x, y, mousestate, .. } => {
                     match naming_search_state {
                         NamingSearchState::ClickingPixels => {
@@ -214,7 +228,7 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                 Event::MouseButtonDown { mouse_btn, x, y, .. } => {
                     if mouse_btn == MouseButton::Right {
                         naming_rect_index = (naming_rect_index + 1) % naming_rect_count;
-                        naming_rect = &main_context.config.naming_rects[naming_rect_index];
+                        naming_rect = &config.naming_rects[naming_rect_index];
                         naming_rect_zoom = Rect::new(naming_rect.zoom.x as i32, naming_rect.zoom.y as i32, naming_rect.zoom.w, naming_rect.zoom.h);
                         naming_rect_crop = Rect::new(naming_rect.crop.x as i32, naming_rect.crop.y as i32, naming_rect.crop.w, naming_rect.crop.h);
                         for pixel in naming_pixels.iter_mut() {
@@ -245,17 +259,24 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
             }
         }
 
-        if waiting_for_search_result && !compute_perform_search_signal.load(Ordering::Relaxed) {
-            waiting_for_search_result = false;
-
-            let search_result = compute_result.lock().unwrap();
-            if search_result.match_count == 1 {
+        if let Some(search_result) = main_context.poll_search_result(&mut compute_job) {
+            if searching_prioritized_seeds && search_result.match_count == 0 {
+                // Nothing matched near the launch time, so it was likely off; try all seeds instead
+                println!("No seeds matched near launch time, searching all seeds");
+                queued_full_search = true;
+            } else if searching_prioritized_seeds && search_result.match_count == 1 {
+                // Only one seed near the launch time matched, but a seed further away could match too, so make sure it's unique among all seeds
+                println!("One seed matched near launch time, searching all seeds to make sure it's unique");
+                queued_full_search = true;
+            } else if search_result.match_count == 1 {
                 // Singular match!
+                let candidate = search_result.candidates[0];
                 rng_fail_match_count = -1;
-                println!("Found seed = {}, pos = {}", search_result.single_matched_seed, search_result.single_matched_position);
+                rng_fail_candidates.clear();
+                println!("Found seed = {}, pos = {}", candidate.seed, candidate.position);
 
                 // Set current RNG for the run
-                main_context.run_context.set_rng(search_result.single_matched_seed, search_result.single_matched_position as usize);
+                main_context.run_context.set_rng(candidate.seed, candidate.position as usize);
 
                 // Progress to next state
                 naming_search_state = NamingSearchState::Found;
@@ -265,7 +286,11 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                 }
             } else {
                 rng_fail_match_count = search_result.match_count as i32;
-                println!("Match count = {}, data1 = {}, data2 = {}", search_result.match_count, search_result.single_matched_seed, search_result.single_matched_position);
+                rng_fail_candidates = search_result.candidates.clone();
+                println!("Match count = {}", search_result.match_count);
+                for candidate in search_result.candidates.iter() {
+                    println!("Candidate seed = {}, pos = {}", candidate.seed, candidate.position);
+                }
             }
         }
 
@@ -312,20 +337,35 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         }
         
         // Perform search if queued
-        if queued_search && !waiting_for_search_result {
+        if (queued_search || queued_full_search) && !compute_job.is_searching() {
+            // Prioritize seeds near the launch time if it was captured (unless that already failed)
+            let launch_seed_clock = main_context.run_context.launch_seed_clock()
+                .filter(|_| queued_search && main_context.config.seed_time_tolerance_ms > 0);
+            if let Some(clock) = launch_seed_clock {
+                // Choose the seeds again if the timing settings changed (e.g. from reloading the config) since they were last chosen
+                let expected_seed = clock.wrapping_add(main_context.config.seed_time_offset_ms);
+                let tolerance = main_context.config.seed_time_tolerance_ms;
+                if prioritized_seeds.is_none() || prioritized_seeds_built_with != (expected_seed, tolerance) {
+                    prioritized_seeds = Some(Arc::new(seed_timing::prioritize_seeds(&unique_seeds.lock().unwrap(), runner_version.rng_15bit(),
+                        expected_seed, tolerance)));
+                    prioritized_seeds_built_with = (expected_seed, tolerance);
+                }
+                searching_prioritized_seeds = true;
+            } else {
+                searching_prioritized_seeds = false;
+            }
             queued_search = false;
+            queued_full_search = false;
 
-            *compute_parameters.lock().unwrap() = NamingSearchParameters {
+            compute_job.begin_search(NamingSearchParameters {
                 search_range: 30_000u32,
+                max_candidates: main_context.config.search_max_candidates,
                 rng_15bit: runner_version.rng_15bit(),
                 rng_signed: runner_version.rng_signed(),
                 rng_old_poly: runner_version.rng_old_poly(),
-                matching_pixels: naming_pixels.iter().map(|p| p.selected).collect()
-            };
-
-            waiting_for_search_result = true;
-            compute_perform_search_signal.store(true, Ordering::Relaxed);
-            compute_join_handle.thread().unpark();
+                matching_pixels: naming_pixels.iter().map(|p| p.selected).collect(),
+                seed_subset: if searching_prioritized_seeds { prioritized_seeds.clone() } else { None }
+            });
         }
 
         // Check for incoming screenshot from the server
@@ -435,11 +475,12 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         // Draw hotkeys
         _ = main_context.font.draw_text_bg(
             main_context, 
-            &format!("[{}] - Screenshot\n[{}] - Raise window\n[{}] - {}\n[{}] - Focus window\n[LMB] - Drag & toggle pixels\n[RMB] - Switch views", 
+            &format!("[{}] - Screenshot\n[{}] - Raise window\n[{}] - {}\n[{}] - Focus window\n[LMB] - Drag & toggle pixels\n[RMB] - Switch views\n[T] - Capture launch time{}", 
                           main_context.config.hotkey_1_name, main_context.config.hotkey_2_name, 
                           main_context.config.hotkey_3_name,
                           if rng_just_found { "Progress to next tool" } else { "Begin search" },
-                          main_context.config.hotkey_4_name), 
+                          main_context.config.hotkey_4_name,
+                          if main_context.run_context.launch_seed_clock().is_some() { " (captured)" } else { "" }), 
             screen_space.x_world_to_screen(8.0), screen_space.y_world_to_screen(8.0),
             0.0, 0.0,
             0, 
@@ -450,9 +491,16 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
 
         // Draw text if search failed
         if rng_fail_match_count != -1 {
+            let mut fail_text = format!("Seed search failed: matched {} seeds/patterns", rng_fail_match_count);
+            for candidate in rng_fail_candidates.iter() {
+                fail_text += &format!("\nSeed {} at position {}", candidate.seed, candidate.position);
+            }
+            if rng_fail_match_count as usize > rng_fail_candidates.len() {
+                fail_text += &format!("\n(and {} more)", rng_fail_match_count as usize - rng_fail_candidates.len());
+            }
             _ = main_context.font.draw_text(
                 main_context, 
-                &format!("Seed search failed: matched {} seeds/patterns", rng_fail_match_count), 
+                &fail_text, 
                 screen_space.x_world_to_screen(8.0), screen_space.y_world_to_screen(WORLD_HEIGHT as f32 - 8.0),
                 0.0, 1.0,
                 0, 
@@ -460,6 +508,9 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                 Color::RGB(255, 0, 0));
         }
 
+        // Draw notice about config reloading, if any
+        _ = program_common::draw_notice(main_context, &screen_space);
+
         // Present latest canvas
         main_context.canvas.present();
 
@@ -0,0 +1,36 @@
+use sdl3::{pixels::Color, rect::Rect, render::{ScaleMode, Texture}};
+
+use crate::{program_common::{rect_from_texture, run_menu, MenuResult}, MainContext, SubProgram};
+
+pub fn run(main_context: &mut MainContext) -> SubProgram {
+    let profile_names: Vec<String> = main_context.config.profile_names().into_iter().map(str::to_string).collect();
+
+    // Mark the active profile (or show a message if there are none)
+    let profile_labels: Vec<String> = profile_names.iter().map(|profile_name| {
+        if main_context.config.active_profile.as_ref() == Some(profile_name) {
+            format!("{} (active)", profile_name)
+        } else {
+            profile_name.clone()
+        }
+    }).collect();
+    let info_text = if profile_names.is_empty() {
+        "No profiles are set up in config.json. See the README for how to add them."
+    } else {
+        "Switching profiles starts a new run (as if resetting)."
+    };
+    let info_surface = main_context.font.render_text_autowrap(info_text, Color::RGB(128, 128, 128), 600).expect("Failed to render text to surface");
+    let mut info_texture = Texture::from_surface(&info_surface, main_context.texture_creator).expect("Failed to create texture from surface");
+    info_texture.set_scale_mode(ScaleMode::Nearest);
+
+    match run_menu(main_context, &profile_labels, |main_context, screen_space| {
+        let info_rect = rect_from_texture(&info_texture);
+        _ = main_context.canvas.copy(&info_texture, info_rect, screen_space.rect_world_to_screen(Rect::new(8, 472 - info_rect.h, info_rect.w as u32, info_rect.h as u32)));
+    }) {
+        MenuResult::Chosen(index) => {
+            main_context.switch_profile(&profile_names[index]);
+            SubProgram::ProgramSelector
+        },
+        MenuResult::Back => SubProgram::ProgramSelector,
+        MenuResult::Exit(program) => program
+    }
+}
@@ -1,20 +1,133 @@
 use std::sync::atomic::Ordering;
 
-use sdl3::{event::Event, keyboard::Keycode, pixels::Color, rect::Rect, render::{ScaleMode, Texture}};
+use sdl3::{event::Event, keyboard::{Keycode, Mod}, pixels::Color, rect::Rect, render::{ScaleMode, Texture}};
 
-use crate::{program_common::{rect_from_texture, FrameTimer, ScreenSpace}, MainContext, SubProgram};
+use crate::{compute_backend::{BoxedSearchBackend, SearchBackend, SearchJob}, config::ConfigRunnerProfile, program_common::{draw_notice, rect_from_texture, FrameTimer, ScreenSpace}, rng::RNG, rng_cache, MainContext, SubProgram};
+
+// Maximum number of RNG values to rewind through when looking for the seed of pasted outputs
+const MAX_REWIND_POSITION: usize = 5_000_000;
+
+// Parses consecutive raw RNG outputs (separated by whitespace or commas)
+fn parse_rng_outputs(text: &str) -> Result<Vec<u32>, &'static str> {
+    let mut outputs: Vec<u32> = Vec::new();
+    for part in text.split(|c: char| c.is_whitespace() || c == ',').filter(|part| !part.is_empty()) {
+        let output = match part.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => part.parse::<u32>()
+        };
+        match output {
+            Ok(output) => outputs.push(output),
+            Err(_) => return Err("Pasted text contains something other than RNG outputs")
+        }
+    }
+    Ok(outputs)
+}
+
+// Progress of recovering the RNG seed and current position from pasted outputs
+#[derive(Clone, Copy, Default)]
+pub enum RngRecoveryState {
+    // Nothing pasted yet (or the last result was already used)
+    #[default]
+    Idle,
+    Searching,
+    Done { seed: u32, position: usize },
+    Failed(&'static str)
+}
+
+// Recovers the RNG seed and current position from consecutive raw RNG outputs.
+// Rewinding through every seed can take several seconds, so this runs on its own thread like the other searches.
+struct RngRecoveryBackend {
+    unique_seeds: Vec<u32>,
+    runner_version: ConfigRunnerProfile
+}
+impl SearchBackend for RngRecoveryBackend {
+    type Parameters = Vec<u32>;
+    type Result = RngRecoveryState;
+
+    fn search(&mut self, outputs: &Vec<u32>) -> Result<RngRecoveryState, &'static str> {
+        let rng = match RNG::from_outputs(outputs, self.runner_version.rng_old_poly()) {
+            Ok(rng) => rng,
+            Err(e) => return Ok(RngRecoveryState::Failed(e))
+        };
+        Ok(match rng.find_seed(&self.unique_seeds, self.runner_version.rng_15bit(), self.runner_version.rng_signed(), MAX_REWIND_POSITION) {
+            // Current position is after all of the pasted outputs
+            Some((seed, position)) => RngRecoveryState::Done { seed, position: position + outputs.len() },
+            None => RngRecoveryState::Failed("Couldn't find an RNG seed for the pasted outputs")
+        })
+    }
+}
+
+// Search thread for recovering the RNG seed, along with the state shown for it
+pub struct RngRecovery {
+    job: SearchJob<Vec<u32>, RngRecoveryState>,
+    runner_version: ConfigRunnerProfile,
+    state: RngRecoveryState
+}
+impl RngRecovery {
+    // Starts the search thread, loading seeds ahead of time so that a paste only needs to wait for the search itself
+    fn spawn(runner_version: ConfigRunnerProfile) -> RngRecovery {
+        let job = SearchJob::spawn(vec![], move |job| {
+            let mut backend: BoxedSearchBackend<Vec<u32>, RngRecoveryState> = Box::new(RngRecoveryBackend {
+                unique_seeds: rng_cache::load_unique_seeds(&runner_version),
+                runner_version
+            });
+            job.run_search_loop(&mut backend);
+        });
+        RngRecovery { job, runner_version, state: RngRecoveryState::Idle }
+    }
+}
+
+// Updates the state of the RNG seed recovery with any finished search, returning it
+fn poll_rng_recovery(main_context: &mut MainContext) -> RngRecoveryState {
+    let Some(mut recovery) = main_context.rng_recovery.take() else { return RngRecoveryState::Idle };
+    let search_result = main_context.poll_search_result(&mut recovery.job).map(|search_result| *search_result);
+    match search_result {
+        Some(state) => recovery.state = state,
+        // The search thread stopped without a result (which gets reported in a notice)
+        None if matches!(recovery.state, RngRecoveryState::Searching) && !recovery.job.is_searching() => {
+            recovery.state = RngRecoveryState::Failed("Search for the RNG seed failed");
+        },
+        None => {}
+    }
+    let state = recovery.state;
+    main_context.rng_recovery = Some(recovery);
+    state
+}
+
+// Shows the given state for the RNG seed recovery, starting a search if there are outputs to search for
+fn set_rng_recovery(main_context: &mut MainContext, state: RngRecoveryState, outputs: Option<Vec<u32>>) {
+    let Some(recovery) = &mut main_context.rng_recovery else { return };
+    if let Some(outputs) = outputs {
+        recovery.job.begin_search(outputs);
+    }
+    recovery.state = state;
+}
 
 pub fn run(main_context: &mut MainContext) -> SubProgram {
     let mut typed_string: String = "".to_owned();
 
     let mut reset_confirmation = false;
 
+    // Keep the search thread from previous visits (unless it's for a different runner version), dropping any old error
+    let runner_version = main_context.config.runner_version;
+    match &mut main_context.rng_recovery {
+        Some(recovery) if recovery.runner_version == runner_version => {
+            if let RngRecoveryState::Failed(_) = recovery.state {
+                recovery.state = RngRecoveryState::Idle;
+            }
+        },
+        _ => main_context.rng_recovery = Some(RngRecovery::spawn(runner_version))
+    }
+
     let mut event_pump = main_context.sdl_context.event_pump().unwrap();
     'running: loop {
         // Handle thread errors
         if main_context.panic_occurred.load(Ordering::Relaxed) {
             break;
         }
+
+        // Pick up any changes to the config
+        main_context.poll_config_reload();
         
         let frame_timer = FrameTimer::start(30);
         let screen_space = ScreenSpace::new(&main_context);
@@ -22,7 +135,7 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         main_context.canvas.set_draw_color(Color::RGB(0, 0, 0));
         main_context.canvas.set_blend_mode(sdl3::render::BlendMode::Blend);
         main_context.canvas.clear();
-        for event in event_pump.poll_iter() {
+        for event in main_context.poll_events(&mut event_pump) {
             match event {
                 Event::Quit {..} => {
                     return SubProgram::None;
@@ -50,6 +163,22 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
                         reset_confirmation = true;
                     }
                 },
+                Event::KeyDown { keycode: Some(Keycode::V), keymod, .. } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    if reset_confirmation || matches!(poll_rng_recovery(main_context), RngRecoveryState::Searching) {
+                        continue;
+                    }
+                    let pasted_text = match main_context.read_clipboard_text() {
+                        Some(text) => text,
+                        None => {
+                            set_rng_recovery(main_context, RngRecoveryState::Failed("Failed to get clipboard text"), None);
+                            continue;
+                        }
+                    };
+                    match parse_rng_outputs(&pasted_text) {
+                        Ok(outputs) => set_rng_recovery(main_context, RngRecoveryState::Searching, Some(outputs)),
+                        Err(e) => set_rng_recovery(main_context, RngRecoveryState::Failed(e), None)
+                    }
+                },
                 Event::KeyDown { keycode: Some(keycode), .. } => {
                     if typed_string.len() >= 5 {
                         continue;
@@ -66,6 +195,15 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
             }
         }
 
+        // Use the recovered RNG state, once the search for it finishes
+        let recovery_state = poll_rng_recovery(main_context);
+        if let RngRecoveryState::Done { seed, position } = recovery_state {
+            println!("Recovered RNG seed {} at position {}", seed, position);
+            main_context.run_context.set_rng(seed, position);
+            set_rng_recovery(main_context, RngRecoveryState::Idle, None);
+            break 'running;
+        }
+
         // Draw the typed string
         if reset_confirmation {
             _ = main_context.font.draw_text(
@@ -89,13 +227,37 @@ pub fn run(main_context: &mut MainContext) -> SubProgram {
         // Draw helpful text
         _ = main_context.font.draw_text(
             main_context, 
-            "Type an RNG seed number, then press Enter.\n\nOr, press Enter with no input to reset\nthe current RNG state in the tool.", 
+            "Type an RNG seed number, then press Enter.\n\nOr, press Enter with no input to reset\nthe current RNG state in the tool.\n\nOr, press Ctrl+V to paste 16 or more consecutive\nraw RNG outputs, to recover the seed and position.", 
             screen_space.x_world_to_screen(8.0), screen_space.y_world_to_screen(8.0),
             0.0, 0.0,
             0, 
             screen_space.scale(), 
             Color::RGB(255, 255, 255));
 
+        // Draw progress or error from pasting RNG outputs, if any
+        if let RngRecoveryState::Searching = recovery_state {
+            _ = main_context.font.draw_text(
+                main_context, 
+                "Searching for the RNG seed...", 
+                screen_space.x_world_to_screen(8.0), screen_space.y_world_to_screen(472.0),
+                0.0, 1.0,
+                0, 
+                screen_space.scale(), 
+                Color::RGB(255, 255, 255));
+        } else if let RngRecoveryState::Failed(paste_error) = recovery_state {
+            _ = main_context.font.draw_text(
+                main_context, 
+                paste_error, 
+                screen_space.x_world_to_screen(8.0), screen_space.y_world_to_screen(472.0),
+                0.0, 1.0,
+                0, 
+                screen_space.scale(), 
+                Color::RGB(255, 0, 0));
+        }
+
+        // Draw notice about config reloading, if any
+        _ = draw_notice(main_context, &screen_space);
+
         // Present latest canvas
         main_context.canvas.present();
 
@@ -1,11 +1,40 @@
-use std::collections::HashSet;
+use std::{collections::{HashMap, HashSet}, sync::Mutex};
 
 const RNG_STATE_SIZE: usize = 16;
 
+// Skips at least this large use jump-ahead matrices, rather than stepping one value at a time
+const JUMP_THRESHOLD: usize = 1 << 14;
+
+// Number of RNG states that an RNGBatch advances together
+pub const RNG_BATCH_LANES: usize = 16;
+
+// Transition matrices for jumping ahead by powers of two, for each random polynomial (built as needed)
+static JUMP_MATRICES: Mutex<Vec<(u32, Vec<JumpMatrix>)>> = Mutex::new(Vec::new());
+
 pub trait LinearRNG: Clone {
     fn next_u32(&mut self) -> u32;
     fn next_f64(&mut self, range: f64) -> f64;
     fn skip(&mut self, amount: usize);
+
+    // Undoes the last call to next_u32, returning the value it returned (or None if already at the first available position)
+    fn prev_u32(&mut self) -> Option<u32>;
+
+    // GameMaker's random(n): a real number from 0 up to (but not including) n
+    fn random(&mut self, n: f64) -> f64 {
+        self.next_f64(n)
+    }
+
+    // GameMaker's irandom(n): a whole number from 0 to n (inclusive), by flooring a double-precision random(n + 1).
+    // Runner versions only differ in seeding and the random polynomial (handled by the RNG itself), while the conversion of
+    // raw values to a range is the same for all of them, so these helpers don't need to know the version.
+    fn irandom(&mut self, n: u32) -> u32 {
+        f64::floor(self.next_f64(n as f64 + 1.0)) as u32
+    }
+}
+
+// GameMaker's round(x), which rounds halfway cases to the nearest even number
+pub fn gm_round(x: f64) -> f64 {
+    f64::round_ties_even(x)
 }
 
 #[derive(Clone)]
@@ -15,13 +44,47 @@ pub struct RNG {
     state: [u32; RNG_STATE_SIZE]
 }
 
+// The RNG state as a 512-bit vector, with words ordered starting from the current index
+type StateBits = [u64; RNG_STATE_SIZE / 2];
+
+// Matrix over GF(2) transforming a state vector, stored as the image of each of the 512 state bits
+#[derive(Clone)]
+struct JumpMatrix {
+    columns: Vec<StateBits>
+}
+
 pub struct PrecomputedRNG {
     values: Vec<u32>
 }
 
+// Many RNG states (one per lane) stored as a structure of arrays, all advanced in lock-step.
+// Every lane shares the same index, so each value generated is the same operation across all lanes.
+#[derive(Clone)]
+pub struct RNGBatch {
+    random_poly: u32,
+    index: usize,
+    state: [[u32; RNG_BATCH_LANES]; RNG_STATE_SIZE],
+    lane_count: usize
+}
+
+// A single lane of an RNGBatch, reading values generated ahead of time by RNGBatch::precompute_lanes,
+// then continuing on from the lane's own state if those run out
+#[derive(Clone)]
+pub struct RNGBatchLane<'a> {
+    values: &'a [u32],
+    position: usize,
+    rng_after_values: RNG
+}
+
+// Precomputed RNG values over a window of positions, which grows on demand from the underlying RNG
+pub struct PrecomputedRNGWindow {
+    values: Vec<u32>,
+    rng_after_values: RNG
+}
+
 #[derive(Clone)]
 pub struct LinearPrecomputedRNG<'a> {
-    precomputed_rng: &'a PrecomputedRNG,
+    values: &'a [u32],
     position: usize
 }
 
@@ -59,6 +122,96 @@ impl RNG {
         rng
     }
 
+    pub fn from_state(state: [u32; RNG_STATE_SIZE], index: usize, use_old_random_poly: bool) -> RNG {
+        RNG {
+            random_poly: if use_old_random_poly { 0xda442d20 } else { 0xda442d24 },
+            index: index & 15,
+            state
+        }
+    }
+
+    // Reconstructs the RNG state from just before the given consecutive raw outputs (at least 16 are needed).
+    // Every output bit is a linear function (over GF(2)) of the state bits, so this solves for the state directly.
+    pub fn from_outputs(outputs: &[u32], use_old_random_poly: bool) -> Result<RNG, &'static str> {
+        if outputs.len() < RNG_STATE_SIZE {
+            return Err("Need at least 16 outputs to recover RNG state");
+        }
+        let mut rng = RNG::from_state([0; RNG_STATE_SIZE], 0, use_old_random_poly);
+
+        // Figure out which output bits each individual state bit affects
+        let state_bit_count = RNG_STATE_SIZE * 32;
+        let state_bit_outputs: Vec<Vec<u32>> = (0..state_bit_count).map(|bit| {
+            let mut basis_rng = rng.clone();
+            basis_rng.state[bit / 32] = 1 << (bit % 32);
+            outputs.iter().map(|_| basis_rng.next_u32()).collect()
+        }).collect();
+
+        // Build one equation per output bit, then solve using Gaussian elimination
+        let mut equations: Vec<(StateBits, bool)> = Vec::with_capacity(outputs.len() * 32);
+        for (i, output) in outputs.iter().enumerate() {
+            for output_bit in 0..32 {
+                let mut coefficients: StateBits = [0; RNG_STATE_SIZE / 2];
+                for (bit, affected_outputs) in state_bit_outputs.iter().enumerate() {
+                    if (affected_outputs[i] >> output_bit) & 1 != 0 {
+                        coefficients[bit / 64] |= 1 << (bit % 64);
+                    }
+                }
+                equations.push((coefficients, (output >> output_bit) & 1 != 0));
+            }
+        }
+        let mut pivot_rows: Vec<usize> = Vec::with_capacity(state_bit_count);
+        for bit in 0..state_bit_count {
+            let (word, mask) = (bit / 64, 1u64 << (bit % 64));
+            let pivot_row = pivot_rows.len();
+            let Some(found_row) = (pivot_row..equations.len()).find(|&row| equations[row].0[word] & mask != 0) else {
+                return Err("Outputs don't determine the full RNG state");
+            };
+            equations.swap(pivot_row, found_row);
+            let pivot = equations[pivot_row];
+            for (row, equation) in equations.iter_mut().enumerate() {
+                if row != pivot_row && equation.0[word] & mask != 0 {
+                    for (coefficient, pivot_coefficient) in equation.0.iter_mut().zip(pivot.0.iter()) {
+                        *coefficient ^= pivot_coefficient;
+                    }
+                    equation.1 ^= pivot.1;
+                }
+            }
+            pivot_rows.push(pivot_row);
+        }
+
+        // Any leftover equations must be consistent with the solution
+        if equations[state_bit_count..].iter().any(|(_, value)| *value) {
+            return Err("Outputs are not from a single RNG sequence");
+        }
+
+        let mut bits: StateBits = [0; RNG_STATE_SIZE / 2];
+        for (bit, row) in pivot_rows.into_iter().enumerate() {
+            if equations[row].1 {
+                bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        rng.set_state_bits(&bits);
+        Ok(rng)
+    }
+
+    // Rewinds the RNG (by up to the given number of values) until it's at the initial state of one of the given seeds.
+    // Returns the seed, and how many values were generated from it to reach the current state.
+    pub fn find_seed(&self, unique_seeds: &[u32], seeds_15bit: bool, seeds_signed: bool, max_position: usize) -> Option<(u32, usize)> {
+        let use_old_random_poly = self.random_poly == 0xda442d20;
+        let initial_states: HashMap<StateBits, u32> = unique_seeds.iter()
+            .map(|&seed| (RNG::new(seed, seeds_15bit, seeds_signed, use_old_random_poly).state_bits(), seed))
+            .collect();
+
+        let mut rng = self.clone();
+        for position in 0..=max_position {
+            if let Some(&seed) = initial_states.get(&rng.state_bits()) {
+                return Some((seed, position));
+            }
+            _ = rng.prev_u32();
+        }
+        None
+    }
+
     pub fn precompute(&mut self, num: usize) -> PrecomputedRNG {
         let mut values: Vec<u32> = Vec::with_capacity(num);
         for _ in 0..num {
@@ -69,43 +222,132 @@ impl RNG {
         }
     }
 
-    pub fn calculate_unique_seeds(seeds_15bit: bool, seeds_signed: bool) -> Vec<u32> {
+    // Copy of this RNG after generating the given number of values, for reading arbitrary positions in O(log n) time
+    pub fn at(&self, position: usize) -> RNG {
+        let mut rng = self.clone();
+        rng.skip(position);
+        rng
+    }
+
+    // The first value generated from a seed while initializing RNG state, which determines the rest of the state.
+    // Seeds are unique (as in calculate_unique_seeds) exactly when this value is unique.
+    pub fn initial_seed_state(seed: u32, seeds_15bit: bool) -> u32 {
+        if seeds_15bit {
+            u32::wrapping_shr(u32::wrapping_add(u32::wrapping_mul(seed, 0x343fd), 0x269ec3), 16) & 0x7fff
+        } else {
+            (i32::wrapping_shr(i32::wrapping_add(i32::wrapping_mul(seed as i32, 0x343fd), 0x269ec3), 16) & 0x7fffffff) as u32
+        }
+    }
+
+    pub fn calculate_unique_seeds(seeds_15bit: bool) -> Vec<u32> {
         let unique_state_count: usize = if seeds_15bit { 32768 } else { 65536 }; 
         let mut unique_seeds_list: Vec<u32> = Vec::with_capacity(unique_state_count);
         let mut unique_states: HashSet<u32> = HashSet::with_capacity(unique_state_count);
 
-        if seeds_15bit {
-            let mut curr_seed: u32 = 0;
-            while unique_states.len() < unique_state_count {
-                let state: u32 = u32::wrapping_shr(u32::wrapping_add(u32::wrapping_mul(curr_seed, 0x343fd), 0x269ec3), 16) & 0x7fff;
-                if unique_states.insert(state) {
-                    unique_seeds_list.push(curr_seed);
-                }
-                curr_seed += 1;
+        let mut curr_seed: u32 = 0;
+        while unique_states.len() < unique_state_count {
+            if unique_states.insert(RNG::initial_seed_state(curr_seed, seeds_15bit)) {
+                unique_seeds_list.push(curr_seed);
             }
-        } else if seeds_signed {
-            let mut curr_seed: u32 = 0;
-            while unique_states.len() < unique_state_count {
-                let state: u32 = (i32::wrapping_shr(i32::wrapping_add(i32::wrapping_mul(curr_seed as i32, 0x343fd), 0x269ec3), 16) & 0x7fffffff) as u32;
-                if unique_states.insert(state) {
-                    unique_seeds_list.push(curr_seed);
+            curr_seed += 1;
+        }
+
+        unique_seeds_list
+    }
+
+    fn state_bits(&self) -> StateBits {
+        let mut bits: StateBits = [0; RNG_STATE_SIZE / 2];
+        for (i, word) in bits.iter_mut().enumerate() {
+            let low = self.state[(self.index + (i * 2)) & 15] as u64;
+            let high = self.state[(self.index + (i * 2) + 1) & 15] as u64;
+            *word = low | (high << 32);
+        }
+        bits
+    }
+
+    fn set_state_bits(&mut self, bits: &StateBits) {
+        for (i, word) in bits.iter().enumerate() {
+            self.state[(self.index + (i * 2)) & 15] = *word as u32;
+            self.state[(self.index + (i * 2) + 1) & 15] = (*word >> 32) as u32;
+        }
+    }
+
+    // Advances the RNG by the given number of values in O(log n) time.
+    // WELL512 is linear over GF(2), so this applies precomputed powers of its transition matrix.
+    fn jump(&mut self, amount: usize) {
+        if amount == 0 {
+            return;
+        }
+        let power_count = (usize::BITS - amount.leading_zeros()) as usize;
+        let mut bits = self.state_bits();
+        JumpMatrix::with_powers(self.random_poly, power_count, |powers| {
+            for (power, matrix) in powers.iter().enumerate() {
+                if (amount >> power) & 1 != 0 {
+                    bits = matrix.apply(&bits);
                 }
-                curr_seed += 1;
             }
-        } else {
-            let mut curr_seed: u32 = 0;
-            while unique_states.len() < unique_state_count {
-                let state: u32 = (i32::wrapping_shr(i32::wrapping_add(i32::wrapping_mul(curr_seed as i32, 0x343fd), 0x269ec3), 16) & 0x7fffffff) as u32;
-                if unique_states.insert(state) {
-                    unique_seeds_list.push(curr_seed);
+        });
+
+        // Each value generated moves the index back by one
+        self.index = (self.index + (amount.wrapping_neg() & 15)) & 15;
+        self.set_state_bits(&bits);
+    }
+}
+
+impl JumpMatrix {
+    // Builds the matrix for generating a single value, by generating a value from each individual state bit
+    fn new_single_step(random_poly: u32) -> JumpMatrix {
+        let mut columns = Vec::with_capacity(RNG_STATE_SIZE * 32);
+        for bit in 0..(RNG_STATE_SIZE * 32) {
+            let mut rng = RNG {
+                random_poly,
+                index: 0,
+                state: [0; RNG_STATE_SIZE]
+            };
+            rng.state[bit / 32] = 1 << (bit % 32);
+            _ = rng.next_u32();
+            columns.push(rng.state_bits());
+        }
+        JumpMatrix { columns }
+    }
+
+    fn apply(&self, bits: &StateBits) -> StateBits {
+        let mut result: StateBits = [0; RNG_STATE_SIZE / 2];
+        for (i, column) in self.columns.iter().enumerate() {
+            if (bits[i / 64] >> (i % 64)) & 1 != 0 {
+                for (result_word, column_word) in result.iter_mut().zip(column.iter()) {
+                    *result_word ^= column_word;
                 }
-                curr_seed += 1;
             }
         }
+        result
+    }
 
-        unique_seeds_list
+    fn squared(&self) -> JumpMatrix {
+        JumpMatrix {
+            columns: self.columns.iter().map(|column| self.apply(column)).collect()
+        }
+    }
+
+    // Calls the given function with at least the given number of matrices, for jumping 1, 2, 4, 8, ... values
+    fn with_powers<F>(random_poly: u32, power_count: usize, func: F) where F: FnOnce(&[JumpMatrix]) {
+        let mut all_matrices = JUMP_MATRICES.lock().unwrap();
+        let poly_index = match all_matrices.iter().position(|(poly, _)| *poly == random_poly) {
+            Some(index) => index,
+            None => {
+                all_matrices.push((random_poly, vec![JumpMatrix::new_single_step(random_poly)]));
+                all_matrices.len() - 1
+            }
+        };
+        let powers = &mut all_matrices[poly_index].1;
+        while powers.len() < power_count {
+            let next_power = powers.last().unwrap().squared();
+            powers.push(next_power);
+        }
+        func(&powers[..power_count]);
     }
 }
+
 impl LinearRNG for RNG {
     fn next_u32(&mut self) -> u32 {
         let mut a: u32 = self.state[self.index];
@@ -124,7 +366,37 @@ impl LinearRNG for RNG {
     fn next_f64(&mut self, range: f64) -> f64 {
         return (self.next_u32() as f64) * 2.3283064365386963e-10 * range;
     }
+    fn prev_u32(&mut self) -> Option<u32> {
+        // Only two state values changed in next_u32; everything else it read is still intact
+        let value = self.state[self.index];
+        let next_index = (self.index + 1) & 15;
+        let a: u32 = self.state[next_index];
+        let mut b: u32 = self.state[(next_index + 9) & 15];
+        b ^= u32::wrapping_shr(b, 11);
+        let c: u32 = a ^ b;
+        let d: u32 = a ^ (u32::wrapping_shl(a, 5) & self.random_poly);
+
+        // Recover the overwritten value at the current index, by inverting x ^ (x << 2)
+        let shifted_xor = value ^ c ^ d ^ u32::wrapping_shl(c, 18) ^ u32::wrapping_shl(b, 28);
+        let mut original = shifted_xor;
+        for _ in 0..16 {
+            original = shifted_xor ^ u32::wrapping_shl(original, 2);
+        }
+        self.state[self.index] = original;
+
+        // Recover the overwritten value at the next index, by inverting x ^ (x << 16)
+        let other = self.state[(next_index + 13) & 15];
+        let shifted_xor = c ^ other ^ u32::wrapping_shl(other, 15);
+        self.state[next_index] = shifted_xor ^ u32::wrapping_shl(shifted_xor, 16);
+
+        self.index = next_index;
+        Some(value)
+    }
     fn skip(&mut self, amount: usize) {
+        if amount >= JUMP_THRESHOLD {
+            self.jump(amount);
+            return;
+        }
         for _ in 0..amount {
             _ = self.next_u32();
         }
@@ -132,8 +404,10 @@ impl LinearRNG for RNG {
 }
 
 impl PrecomputedRNG {
-    pub fn get_u32(&self, position: usize) -> u32 {
-        self.values[position]
+    pub fn from_values(values: Vec<u32>) -> PrecomputedRNG {
+        PrecomputedRNG {
+            values
+        }
     }
     pub fn get_f64(&self, range: f64, position: usize) -> f64 {
         return (self.values[position] as f64) * 2.3283064365386963e-10 * range;
@@ -143,26 +417,476 @@ impl PrecomputedRNG {
     }
 }
 
+impl RNGBatch {
+    pub fn new(seeds: &[u32], seeds_15bit: bool, seeds_signed: bool, use_old_random_poly: bool) -> RNGBatch {
+        let rngs: Vec<RNG> = seeds.iter().map(|&seed| RNG::new(seed, seeds_15bit, seeds_signed, use_old_random_poly)).collect();
+        RNGBatch::from_rngs(&rngs)
+    }
+
+    // Combines up to RNG_BATCH_LANES RNGs into a batch. They must all be at the same index, with the same polynomial.
+    // Any unused lanes are filled with copies of the first RNG.
+    pub fn from_rngs(rngs: &[RNG]) -> RNGBatch {
+        assert!(!rngs.is_empty() && rngs.len() <= RNG_BATCH_LANES, "Batch needs between 1 and {} RNGs", RNG_BATCH_LANES);
+        let first = &rngs[0];
+        assert!(rngs.iter().all(|rng| rng.index == first.index && rng.random_poly == first.random_poly), "Batched RNGs must be in lock-step");
+        let mut batch = RNGBatch {
+            random_poly: first.random_poly,
+            index: first.index,
+            state: [[0; RNG_BATCH_LANES]; RNG_STATE_SIZE],
+            lane_count: rngs.len()
+        };
+        for lane in 0..RNG_BATCH_LANES {
+            batch.set_lane(lane, rngs.get(lane).unwrap_or(first));
+        }
+        batch
+    }
+
+    pub fn lane_count(&self) -> usize {
+        self.lane_count
+    }
+
+    // Extracts the current state of a single lane
+    pub fn lane(&self, lane: usize) -> RNG {
+        RNG {
+            random_poly: self.random_poly,
+            index: self.index,
+            state: std::array::from_fn(|i| self.state[i][lane])
+        }
+    }
+
+    fn set_lane(&mut self, lane: usize, rng: &RNG) {
+        for (state_lanes, value) in self.state.iter_mut().zip(rng.state.iter()) {
+            state_lanes[lane] = *value;
+        }
+    }
+
+    // Same as RNG::next_u32, for every lane at once
+    pub fn next_u32(&mut self) -> [u32; RNG_BATCH_LANES] {
+        let index_13 = (self.index + 13) & 15;
+        let index_9 = (self.index + 9) & 15;
+        let next_index = (self.index + 15) & 15;
+        let mut c_lanes = [0; RNG_BATCH_LANES];
+        let mut b_lanes = [0; RNG_BATCH_LANES];
+        for lane in 0..RNG_BATCH_LANES {
+            let a: u32 = self.state[self.index][lane];
+            let b: u32 = self.state[index_13][lane];
+            c_lanes[lane] = a ^ b ^ u32::wrapping_shl(a, 16) ^ u32::wrapping_shl(b, 15);
+            let b: u32 = self.state[index_9][lane];
+            b_lanes[lane] = b ^ u32::wrapping_shr(b, 11);
+        }
+        let mut values = [0; RNG_BATCH_LANES];
+        for lane in 0..RNG_BATCH_LANES {
+            let (b, c) = (b_lanes[lane], c_lanes[lane]);
+            let a: u32 = c ^ b;
+            self.state[self.index][lane] = a;
+            let d: u32 = a ^ (u32::wrapping_shl(a, 5) & self.random_poly);
+            let a: u32 = self.state[next_index][lane];
+            values[lane] = a ^ c ^ d ^ u32::wrapping_shl(a, 2) ^ u32::wrapping_shl(c, 18) ^ u32::wrapping_shl(b, 28);
+        }
+        self.state[next_index] = values;
+        self.index = next_index;
+        values
+    }
+
+    // Same as RNG::skip, for every lane at once
+    pub fn skip(&mut self, amount: usize) {
+        if amount >= JUMP_THRESHOLD {
+            for lane in 0..RNG_BATCH_LANES {
+                let mut rng = self.lane(lane);
+                rng.jump(amount);
+                self.set_lane(lane, &rng);
+            }
+            self.index = (self.index + (amount.wrapping_neg() & 15)) & 15;
+            return;
+        }
+        for _ in 0..amount {
+            _ = self.next_u32();
+        }
+    }
+
+    // Generates the given number of values for every lane, stored one lane after another (to be read using lane_reader)
+    pub fn precompute_lanes(&mut self, count: usize) -> Vec<u32> {
+        let mut values: Vec<u32> = vec![0; count * RNG_BATCH_LANES];
+        for i in 0..count {
+            for (lane, value) in self.next_u32().into_iter().enumerate() {
+                values[(lane * count) + i] = value;
+            }
+        }
+        values
+    }
+
+    // Reads a single lane's values from precompute_lanes, which must have been the last thing to advance this batch
+    pub fn lane_reader<'a>(&self, values: &'a [u32], lane: usize) -> RNGBatchLane<'a> {
+        let count = values.len() / RNG_BATCH_LANES;
+        RNGBatchLane {
+            values: &values[(lane * count)..((lane + 1) * count)],
+            position: 0,
+            rng_after_values: self.lane(lane)
+        }
+    }
+}
+
+impl LinearRNG for RNGBatchLane<'_> {
+    fn next_u32(&mut self) -> u32 {
+        let value = match self.values.get(self.position) {
+            Some(&value) => value,
+            None => self.rng_after_values.next_u32()
+        };
+        self.position += 1;
+        value
+    }
+    fn next_f64(&mut self, range: f64) -> f64 {
+        (self.next_u32() as f64) * 2.3283064365386963e-10 * range
+    }
+    fn skip(&mut self, amount: usize) {
+        let skipped_values = usize::min(amount, self.values.len().saturating_sub(self.position));
+        self.rng_after_values.skip(amount - skipped_values);
+        self.position += amount;
+    }
+    fn prev_u32(&mut self) -> Option<u32> {
+        self.position = self.position.checked_sub(1)?;
+        match self.values.get(self.position) {
+            Some(&value) => Some(value),
+            None => self.rng_after_values.prev_u32()
+        }
+    }
+}
+
+impl PrecomputedRNGWindow {
+    // Creates a window starting with already-precomputed values, given the RNG state just after them
+    pub fn from_precomputed(precomputed_rng: PrecomputedRNG, rng_after_values: RNG) -> PrecomputedRNGWindow {
+        PrecomputedRNGWindow {
+            values: precomputed_rng.values,
+            rng_after_values
+        }
+    }
+
+    // Position just after the last value computed so far
+    pub fn end(&self) -> usize {
+        self.values.len()
+    }
+
+    // Computes values up until (but not including) the given position, if not already computed
+    pub fn extend_to(&mut self, end: usize) {
+        let count = end.saturating_sub(self.end());
+        self.values.reserve(count);
+        for _ in 0..count {
+            self.values.push(self.rng_after_values.next_u32());
+        }
+    }
+
+    // Reads values in order starting from the given position, up until the current end of the window
+    pub fn linear(&self, position: usize) -> Result<LinearPrecomputedRNG<'_>, &'static str> {
+        if position >= self.end() {
+            return Err("RNG position is beyond precomputed window");
+        }
+        Ok(LinearPrecomputedRNG {
+            values: &self.values,
+            position
+        })
+    }
+}
+
 impl LinearPrecomputedRNG<'_> {
     pub fn new<'a>(precomputed_rng: &'a PrecomputedRNG, position: usize) -> LinearPrecomputedRNG<'a> {
         LinearPrecomputedRNG {
-            precomputed_rng,
+            values: &precomputed_rng.values,
             position
         }
     }
 }
 impl LinearRNG for LinearPrecomputedRNG<'_> {
     fn next_u32(&mut self) -> u32 {
-        let value = self.precomputed_rng.get_u32(self.position);
+        let value = self.values[self.position];
         self.position += 1;
         value
     }
     fn next_f64(&mut self, range: f64) -> f64 {
-        let value = self.precomputed_rng.get_f64(range, self.position);
-        self.position += 1;
-        value
+        (self.next_u32() as f64) * 2.3283064365386963e-10 * range
     }
     fn skip(&mut self, amount: usize) {
         self.position += amount;
     }
-}
\ No newline at end of file
+    fn prev_u32(&mut self) -> Option<u32> {
+        self.position = self.position.checked_sub(1)?;
+        Some(self.values[self.position])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigRunnerVersion;
+
+    const RUNNER_VERSIONS: [ConfigRunnerVersion; 4] = [
+        ConfigRunnerVersion::Undertale_Windows_v1_0,
+        ConfigRunnerVersion::Undertale_Windows_v1_001,
+        ConfigRunnerVersion::Undertale_Linux_v1_001,
+        ConfigRunnerVersion::Undertale_Windows_v1_08
+    ];
+
+    fn new_rng(seed: u32, runner_version: &ConfigRunnerVersion) -> RNG {
+        RNG::new(seed, runner_version.rng_15bit(), runner_version.rng_signed(), runner_version.rng_old_poly())
+    }
+
+    fn step(rng: &mut RNG, amount: usize) {
+        for _ in 0..amount {
+            _ = rng.next_u32();
+        }
+    }
+
+    #[test]
+    fn jump_matches_stepping() {
+        for runner_version in RUNNER_VERSIONS.iter() {
+            for amount in [0, 1, 2, 15, 16, 17, 31, 100, 1000, 12345] {
+                let mut stepped = new_rng(1234, runner_version);
+                let mut jumped = stepped.clone();
+                step(&mut stepped, amount);
+                jumped.jump(amount);
+                assert_eq!(stepped.index, jumped.index, "index mismatch after {} values", amount);
+                assert_eq!(stepped.state, jumped.state, "state mismatch after {} values", amount);
+                assert_eq!(stepped.next_u32(), jumped.next_u32());
+            }
+        }
+    }
+
+    #[test]
+    fn jump_matches_stepping_from_any_index() {
+        for runner_version in RUNNER_VERSIONS.iter() {
+            let mut start = new_rng(98765, runner_version);
+            for _ in 0..RNG_STATE_SIZE {
+                _ = start.next_u32();
+                let mut stepped = start.clone();
+                let mut jumped = start.clone();
+                step(&mut stepped, 777);
+                jumped.jump(777);
+                assert_eq!(stepped.index, jumped.index);
+                assert_eq!(stepped.state, jumped.state);
+            }
+        }
+    }
+
+    #[test]
+    fn skip_matches_stepping() {
+        for runner_version in RUNNER_VERSIONS.iter() {
+            for amount in [JUMP_THRESHOLD - 1, JUMP_THRESHOLD, 250_001] {
+                let mut stepped = new_rng(42, runner_version);
+                let mut skipped = stepped.clone();
+                step(&mut stepped, amount);
+                skipped.skip(amount);
+                for _ in 0..32 {
+                    assert_eq!(stepped.next_u32(), skipped.next_u32(), "value mismatch after skipping {}", amount);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn prev_undoes_next() {
+        for runner_version in RUNNER_VERSIONS.iter() {
+            for seed in [0, 1, 777, 0x7fffffff, 0xffffffff] {
+                let start = new_rng(seed, runner_version);
+                let mut rng = start.clone();
+                let values: Vec<u32> = (0..1000).map(|_| rng.next_u32()).collect();
+                for &value in values.iter().rev() {
+                    assert_eq!(rng.prev_u32(), Some(value));
+                }
+                assert_eq!(rng.index, start.index);
+                assert_eq!(rng.state, start.state);
+            }
+        }
+    }
+
+    #[test]
+    fn prev_then_next_round_trips() {
+        for runner_version in RUNNER_VERSIONS.iter() {
+            let mut rng = new_rng(31337, runner_version);
+            rng.skip(500);
+            let before = rng.clone();
+            for _ in 0..100 {
+                _ = rng.prev_u32();
+            }
+            for _ in 0..100 {
+                _ = rng.next_u32();
+            }
+            assert_eq!(rng.index, before.index);
+            assert_eq!(rng.state, before.state);
+        }
+    }
+
+    #[test]
+    fn precomputed_prev_undoes_next() {
+        let precomputed = new_rng(9, &ConfigRunnerVersion::Undertale_Windows_v1_001).precompute(100);
+        let mut lprng = LinearPrecomputedRNG::new(&precomputed, 50);
+        let value = lprng.next_u32();
+        assert_eq!(lprng.prev_u32(), Some(value));
+        assert_eq!(lprng.position, 50);
+
+        // Nothing to go back to from the first position
+        let mut lprng = LinearPrecomputedRNG::new(&precomputed, 0);
+        assert_eq!(lprng.prev_u32(), None);
+        assert_eq!(lprng.position, 0);
+        assert_eq!(lprng.next_u32(), precomputed.raw()[0]);
+    }
+
+    #[test]
+    fn recovers_state_from_outputs() {
+        for runner_version in RUNNER_VERSIONS.iter() {
+            let mut rng = new_rng(4321, runner_version);
+            rng.skip(1234);
+            let expected = rng.clone();
+            for output_count in [16, 17, 40] {
+                let outputs: Vec<u32> = {
+                    let mut rng = expected.clone();
+                    (0..output_count).map(|_| rng.next_u32()).collect()
+                };
+                let mut recovered = RNG::from_outputs(&outputs, runner_version.rng_old_poly()).unwrap();
+                assert_eq!(recovered.state_bits(), expected.state_bits());
+                for &output in outputs.iter() {
+                    assert_eq!(recovered.next_u32(), output);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_outputs() {
+        assert!(RNG::from_outputs(&[1; 15], false).is_err());
+
+        let mut rng = new_rng(1, &ConfigRunnerVersion::Undertale_Windows_v1_001);
+        let mut outputs: Vec<u32> = (0..20).map(|_| rng.next_u32()).collect();
+        outputs[18] ^= 1;
+        assert!(RNG::from_outputs(&outputs, false).is_err());
+    }
+
+    #[test]
+    fn finds_seed_and_position_from_outputs() {
+        for runner_version in RUNNER_VERSIONS.iter() {
+            let unique_seeds = RNG::calculate_unique_seeds(runner_version.rng_15bit());
+            let seed = unique_seeds[unique_seeds.len() / 3];
+            let mut rng = new_rng(seed, runner_version);
+            rng.skip(2500);
+            let outputs: Vec<u32> = (0..16).map(|_| rng.next_u32()).collect();
+
+            let recovered = RNG::from_outputs(&outputs, runner_version.rng_old_poly()).unwrap();
+            let found = recovered.find_seed(&unique_seeds, runner_version.rng_15bit(), runner_version.rng_signed(), 5000);
+            assert_eq!(found, Some((seed, 2500)));
+            assert_eq!(recovered.find_seed(&unique_seeds, runner_version.rng_15bit(), runner_version.rng_signed(), 2000), None);
+        }
+    }
+
+    #[test]
+    fn finds_seed_from_state() {
+        let runner_version = ConfigRunnerVersion::Undertale_Windows_v1_08;
+        let unique_seeds = RNG::calculate_unique_seeds(runner_version.rng_15bit());
+        let mut rng = new_rng(unique_seeds[10], &runner_version);
+        rng.skip(37);
+        let from_state = RNG::from_state(rng.state, rng.index, runner_version.rng_old_poly());
+        assert_eq!(from_state.find_seed(&unique_seeds, runner_version.rng_15bit(), runner_version.rng_signed(), 100), Some((unique_seeds[10], 37)));
+    }
+
+    #[test]
+    fn irandom_floors_random() {
+        for runner_version in RUNNER_VERSIONS.iter() {
+            let mut rng = new_rng(2024, runner_version);
+            for n in [0, 1, 3, 14, 29, 255, 100_000] {
+                // Same conversion from the raw value for every runner version
+                let mut expected_rng = rng.clone();
+                let expected = f64::floor((expected_rng.next_u32() as f64) * 2.3283064365386963e-10 * (n as f64 + 1.0)) as u32;
+                let value = rng.irandom(n);
+                assert_eq!(value, expected);
+                assert!(value <= n);
+            }
+        }
+    }
+
+    #[test]
+    fn irandom_covers_whole_range() {
+        let mut rng = new_rng(7, &ConfigRunnerVersion::Undertale_Windows_v1_001);
+        let mut seen = [false; 4];
+        for _ in 0..1000 {
+            seen[rng.irandom(3) as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+    }
+
+    #[test]
+    fn gm_round_rounds_halves_to_even() {
+        assert_eq!(gm_round(0.5), 0.0);
+        assert_eq!(gm_round(1.5), 2.0);
+        assert_eq!(gm_round(2.5), 2.0);
+        assert_eq!(gm_round(2.6), 3.0);
+        assert_eq!(gm_round(29.5), 30.0);
+    }
+
+    #[test]
+    fn batch_matches_individual_rngs() {
+        for runner_version in RUNNER_VERSIONS.iter() {
+            let seeds: Vec<u32> = (0..(RNG_BATCH_LANES as u32 - 3)).map(|i| i * 1009).collect();
+            let mut rngs: Vec<RNG> = seeds.iter().map(|&seed| new_rng(seed, runner_version)).collect();
+            let mut batch = RNGBatch::new(&seeds, runner_version.rng_15bit(), runner_version.rng_signed(), runner_version.rng_old_poly());
+            assert_eq!(batch.lane_count(), seeds.len());
+            for amount in [0, 5, JUMP_THRESHOLD + 3] {
+                batch.skip(amount);
+                for rng in rngs.iter_mut() {
+                    rng.skip(amount);
+                }
+                for _ in 0..100 {
+                    let values = batch.next_u32();
+                    for (lane, rng) in rngs.iter_mut().enumerate() {
+                        assert_eq!(values[lane], rng.next_u32(), "lane {} mismatch after skipping {}", lane, amount);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn batch_lane_reader_continues_past_values() {
+        let seeds: Vec<u32> = (0..RNG_BATCH_LANES as u32).collect();
+        let mut batch = RNGBatch::new(&seeds, false, false, false);
+        let values = batch.precompute_lanes(50);
+        for (lane, &seed) in seeds.iter().enumerate() {
+            let mut rng = RNG::new(seed, false, false, false);
+            let mut reader = batch.lane_reader(&values, lane);
+            let expected: Vec<u32> = (0..80).map(|_| rng.next_u32()).collect();
+            assert_eq!((0..80).map(|_| reader.next_u32()).collect::<Vec<u32>>(), expected);
+            for &value in expected.iter().rev() {
+                assert_eq!(reader.prev_u32(), Some(value));
+            }
+            assert_eq!(reader.prev_u32(), None);
+            reader.skip(70);
+            assert_eq!(reader.next_u32(), expected[70]);
+        }
+    }
+
+    #[test]
+    fn window_extends_values() {
+        let mut rng = RNG::new(2024, false, false, false);
+        let expected: Vec<u32> = (0..500).map(|_| rng.next_u32()).collect();
+
+        let mut start_rng = RNG::new(2024, false, false, false);
+        let mut window = PrecomputedRNGWindow::from_precomputed(start_rng.precompute(100), start_rng);
+        let value_at = |window: &PrecomputedRNGWindow, position: usize| window.linear(position).map(|mut linear| linear.next_u32());
+        assert_eq!(value_at(&window, 0), Ok(expected[0]));
+        assert_eq!(value_at(&window, 100), Err("RNG position is beyond precomputed window"));
+        window.extend_to(300);
+        assert_eq!(value_at(&window, 299), Ok(expected[299]));
+        window.extend_to(500);
+        let mut linear = window.linear(100).unwrap();
+        for &value in expected[100..500].iter() {
+            assert_eq!(linear.next_u32(), value);
+        }
+    }
+
+    #[test]
+    fn at_matches_precomputed_positions() {
+        for runner_version in RUNNER_VERSIONS.iter() {
+            let rng = new_rng(5, runner_version);
+            let precomputed = rng.clone().precompute(100_000);
+            for position in [0, 1, 16_384, 65_535, 99_999] {
+                assert_eq!(rng.at(position).next_u32(), precomputed.raw()[position]);
+            }
+        }
+    }
+}
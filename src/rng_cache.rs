@@ -0,0 +1,345 @@
+use std::{fs::{self, File}, io::{Seek, SeekFrom, Write}, path::{Path, PathBuf}, time::SystemTime};
+
+use crate::{config::ConfigRunnerProfile, rng::{LinearRNG, PrecomputedRNG, PrecomputedRNGWindow, RNG}, util};
+
+// Increase whenever the file layout (or how anything in it is calculated) changes, to regenerate old files
+const CACHE_FORMAT_VERSION: u32 = 1;
+
+const CACHE_MAGIC: &[u8; 4] = b"DMRC";
+const CACHE_HEADER_SIZE: usize = 44;
+
+const CACHE_KIND_UNIQUE_SEEDS: u32 = 0;
+const CACHE_KIND_PRECOMPUTED_RNG: u32 = 1;
+
+// Offsets of header fields that change when values are appended to a cache file
+const CACHE_COUNT_OFFSET: usize = 28;
+const CACHE_CHECKSUM_OFFSET: usize = 36;
+
+// Most RNG values kept in a single precomputed RNG file (32MB). A cached range is extended to cover new requests near it,
+// but requests that would take it past this size replace it instead.
+const MAX_CACHED_RNG_VALUES: usize = 8 * 1024 * 1024;
+
+// Total size of precomputed RNG files to keep, past which the least recently used ones get deleted
+const MAX_PRECOMPUTED_RNG_CACHE_SIZE: u64 = 256 * 1024 * 1024;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+// Everything identifying a cache file's contents, other than the values themselves
+#[derive(Clone, Copy, PartialEq)]
+struct CacheKey {
+    kind: u32,
+    profile_flags: u32,
+    seed: u32
+}
+
+fn profile_flags(profile: &ConfigRunnerProfile) -> u32 {
+    (profile.rng_15bit() as u32) | ((profile.rng_signed() as u32) << 1) | ((profile.rng_old_poly() as u32) << 2)
+}
+
+// 64-bit FNV-1a hash of the cached values
+fn checksum(values: &[u32]) -> u64 {
+    continue_checksum(FNV_OFFSET_BASIS, values)
+}
+
+// Continues a checksum with more values, giving the same result as a checksum of all of the values at once
+fn continue_checksum(mut hash: u64, values: &[u32]) -> u64 {
+    for value in values {
+        for byte in value.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+// Encodes a cache file, for the given values starting at the given RNG position
+fn encode(key: CacheKey, start: u64, values: &[u32]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(CACHE_HEADER_SIZE + (values.len() * 4));
+    bytes.extend_from_slice(CACHE_MAGIC);
+    bytes.extend_from_slice(&CACHE_FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&key.kind.to_le_bytes());
+    bytes.extend_from_slice(&key.profile_flags.to_le_bytes());
+    bytes.extend_from_slice(&key.seed.to_le_bytes());
+    bytes.extend_from_slice(&start.to_le_bytes());
+    bytes.extend_from_slice(&(values.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&checksum(values).to_le_bytes());
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+// Decodes a cache file (into its starting RNG position and values), verifying it's for the expected key and that its values weren't corrupted
+fn decode(bytes: &[u8], expected_key: CacheKey) -> Result<(u64, Vec<u32>), &'static str> {
+    if bytes.len() < CACHE_HEADER_SIZE || &bytes[0..4] != CACHE_MAGIC {
+        return Err("not a cache file");
+    }
+    let read_u32 = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    let read_u64 = |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+    if read_u32(4) != CACHE_FORMAT_VERSION {
+        return Err("outdated format version");
+    }
+    let key = CacheKey {
+        kind: read_u32(8),
+        profile_flags: read_u32(12),
+        seed: read_u32(16)
+    };
+    if key != expected_key {
+        return Err("cached for different parameters");
+    }
+    // A corrupt count could be large enough to overflow the expected size
+    let expected_size = usize::try_from(read_u64(CACHE_COUNT_OFFSET)).ok()
+        .and_then(|count| count.checked_mul(4))
+        .and_then(|size| size.checked_add(CACHE_HEADER_SIZE));
+    if expected_size != Some(bytes.len()) {
+        return Err("unexpected file size");
+    }
+    let values: Vec<u32> = bytes[CACHE_HEADER_SIZE..].chunks_exact(4)
+        .map(|value| u32::from_le_bytes(value.try_into().unwrap()))
+        .collect();
+    if checksum(&values) != read_u64(CACHE_CHECKSUM_OFFSET) {
+        return Err("checksum mismatch");
+    }
+    Ok((read_u64(20), values))
+}
+
+fn cache_directory() -> PathBuf {
+    util::get_exe_directory().join("rng_cache")
+}
+
+fn cache_path(file_name: String) -> PathBuf {
+    cache_directory().join(file_name)
+}
+
+fn read_cache(path: &Path, key: CacheKey) -> Result<(u64, Vec<u32>), &'static str> {
+    let bytes = fs::read(path).map_err(|_| "couldn't read file")?;
+    decode(&bytes, key)
+}
+
+fn write_cache(path: &Path, key: CacheKey, start: u64, values: &[u32]) {
+    // Write to a temporary file first, so a partially-written cache never gets loaded
+    let temp_path = path.with_extension("tmp");
+    let result = path.parent().map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&temp_path, encode(key, start, values)))
+        .and_then(|_| fs::rename(&temp_path, path));
+    if let Err(e) = result {
+        eprintln!("Failed to write RNG cache file {}: {}", path.display(), e);
+    }
+}
+
+// Adds values to the end of an existing cache file (which currently holds the given values), updating its header to match
+fn append_cache(path: &Path, values: &[u32], new_values: &[u32]) {
+    let result = File::options().write(true).open(path).and_then(|mut file| {
+        // If this gets cut off partway, the file size won't match the header anymore, so the file just gets regenerated later
+        let mut bytes: Vec<u8> = Vec::with_capacity(new_values.len() * 4);
+        for value in new_values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&bytes)?;
+        file.seek(SeekFrom::Start(CACHE_COUNT_OFFSET as u64))?;
+        file.write_all(&((values.len() + new_values.len()) as u64).to_le_bytes())?;
+        file.write_all(&continue_checksum(checksum(values), new_values).to_le_bytes())
+    });
+    if let Err(e) = result {
+        eprintln!("Failed to extend RNG cache file {}: {}", path.display(), e);
+    }
+}
+
+// Marks a cache file as just used, for deciding which files to delete first
+fn touch_cache(path: &Path) {
+    _ = File::options().write(true).open(path).and_then(|file| file.set_modified(SystemTime::now()));
+}
+
+// Deletes the least recently used precomputed RNG files in the directory, while they add up to more than the given size.
+// The file currently in use is always kept.
+fn evict_precomputed_rng(directory: &Path, max_size: u64, in_use: &Path) {
+    let Ok(entries) = fs::read_dir(directory) else { return };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries.filter_map(|entry| {
+        let entry = entry.ok()?;
+        let file_name = entry.file_name().into_string().ok()?;
+        if !file_name.starts_with("rng_") || !file_name.ends_with(".bin") {
+            return None;
+        }
+        let metadata = entry.metadata().ok()?;
+        Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+    }).collect();
+    files.sort_by_key(|(modified, _, _)| *modified);
+
+    let mut total_size: u64 = files.iter().map(|(_, size, _)| size).sum();
+    for (_, size, path) in files {
+        if total_size <= max_size {
+            break;
+        }
+        if path == in_use {
+            continue;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                eprintln!("Deleted least recently used RNG cache file {}", path.display());
+                total_size -= size;
+            },
+            Err(e) => eprintln!("Failed to delete RNG cache file {}: {}", path.display(), e)
+        }
+    }
+}
+
+// Loads the unique seed table for the given runner profile, calculating (and caching) it if necessary
+pub fn load_unique_seeds(profile: &ConfigRunnerProfile) -> Vec<u32> {
+    let key = CacheKey {
+        kind: CACHE_KIND_UNIQUE_SEEDS,
+        profile_flags: profile_flags(profile),
+        seed: 0
+    };
+    let path = cache_path(format!("seeds_{}.bin", key.profile_flags));
+    match read_cache(&path, key) {
+        Ok((_, unique_seeds)) => unique_seeds,
+        Err(e) => {
+            eprintln!("Regenerating unique seed table ({})", e);
+            let unique_seeds = RNG::calculate_unique_seeds(profile.rng_15bit());
+            write_cache(&path, key, 0, &unique_seeds);
+            unique_seeds
+        }
+    }
+}
+
+// Loads RNG values for the given seed, starting at the given position, calculating (and caching) them if necessary.
+// One file is kept per seed, whose range gets extended whenever it doesn't cover the requested range (computing only the
+// missing values), since runs keep moving forward from where they started.
+pub fn load_precomputed_rng(profile: &ConfigRunnerProfile, seed: u32, start: usize, count: usize) -> PrecomputedRNG {
+    let key = CacheKey {
+        kind: CACHE_KIND_PRECOMPUTED_RNG,
+        profile_flags: profile_flags(profile),
+        seed
+    };
+    let path = cache_path(format!("rng_{}_{}.bin", key.profile_flags, seed));
+    let end = start + count;
+    let new_rng = || RNG::new(seed, profile.rng_15bit(), profile.rng_signed(), profile.rng_old_poly());
+    match read_cache(&path, key) {
+        Ok((cached_start, values)) => {
+            let cached_start = cached_start as usize;
+            let cached_end = cached_start + values.len();
+            if cached_start <= start && cached_end >= end {
+                touch_cache(&path);
+                let offset = start - cached_start;
+                return PrecomputedRNG::from_values(values[offset..(offset + count)].to_vec());
+            }
+
+            let merged_start = usize::min(start, cached_start);
+            let merged_end = usize::max(end, cached_end);
+            if merged_end - merged_start <= MAX_CACHED_RNG_VALUES {
+                eprintln!("Extending precomputed RNG (cached range {}..{} doesn't cover {}..{})", cached_start, cached_end, start, end);
+                let mut rng = new_rng();
+                rng.skip(cached_end);
+                let after = rng.precompute(merged_end - cached_end);
+                let merged_values = if merged_start < cached_start {
+                    let mut rng = new_rng();
+                    rng.skip(merged_start);
+                    let merged_values = [rng.precompute(cached_start - merged_start).raw().as_slice(), &values, after.raw()].concat();
+                    write_cache(&path, key, merged_start as u64, &merged_values);
+                    merged_values
+                } else {
+                    append_cache(&path, &values, after.raw());
+                    [values.as_slice(), after.raw()].concat()
+                };
+                evict_precomputed_rng(&cache_directory(), MAX_PRECOMPUTED_RNG_CACHE_SIZE, &path);
+                let offset = start - merged_start;
+                return PrecomputedRNG::from_values(merged_values[offset..(offset + count)].to_vec());
+            }
+            eprintln!("Regenerating precomputed RNG (cached range {}..{} is too far from {}..{})", cached_start, cached_end, start, end);
+        },
+        Err(e) => eprintln!("Regenerating precomputed RNG ({})", e)
+    }
+
+    let mut rng = new_rng();
+    rng.skip(start);
+    let precomputed_rng = rng.precompute(count);
+    write_cache(&path, key, start as u64, precomputed_rng.raw());
+    evict_precomputed_rng(&cache_directory(), MAX_PRECOMPUTED_RNG_CACHE_SIZE, &path);
+    precomputed_rng
+}
+
+// Same as load_precomputed_rng, but as a window that can keep growing past the given count afterwards
+pub fn load_precomputed_rng_window(profile: &ConfigRunnerProfile, seed: u32, start: usize, count: usize) -> PrecomputedRNGWindow {
+    let precomputed_rng = load_precomputed_rng(profile, seed, start, count);
+    let mut rng_after_values = RNG::new(seed, profile.rng_15bit(), profile.rng_signed(), profile.rng_old_poly());
+    rng_after_values.skip(start + count);
+    PrecomputedRNGWindow::from_precomputed(precomputed_rng, rng_after_values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: CacheKey = CacheKey {
+        kind: CACHE_KIND_PRECOMPUTED_RNG,
+        profile_flags: 0b101,
+        seed: 1234
+    };
+
+    #[test]
+    fn round_trips_values() {
+        let values: Vec<u32> = (0..1000).map(|i: u32| i.wrapping_mul(2654435761)).collect();
+        assert_eq!(decode(&encode(KEY, 5678, &values), KEY), Ok((5678, values)));
+    }
+
+    #[test]
+    fn rejects_corrupted_or_mismatched_files() {
+        let values: Vec<u32> = (0..100).collect();
+        let bytes = encode(KEY, 0, &values);
+
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert_eq!(decode(&corrupted, KEY), Err("checksum mismatch"));
+
+        assert_eq!(decode(&bytes[..bytes.len() - 4], KEY), Err("unexpected file size"));
+
+        let mut huge_count = bytes.clone();
+        huge_count[CACHE_COUNT_OFFSET..(CACHE_COUNT_OFFSET + 8)].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(decode(&huge_count, KEY), Err("unexpected file size"));
+
+        assert_eq!(decode(&bytes, CacheKey { seed: 1235, ..KEY }), Err("cached for different parameters"));
+
+        let mut outdated = bytes.clone();
+        outdated[4..8].copy_from_slice(&(CACHE_FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(decode(&outdated, KEY), Err("outdated format version"));
+    }
+
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("dust_manipulator_{}_{}", name, std::process::id()));
+        _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn appends_values() {
+        let directory = test_directory("append");
+        let path = directory.join("rng_5_1234.bin");
+        let values: Vec<u32> = (0..100).collect();
+        let new_values: Vec<u32> = (100..250).collect();
+        write_cache(&path, KEY, 40, &values);
+        append_cache(&path, &values, &new_values);
+        assert_eq!(read_cache(&path, KEY), Ok((40, (0..250).collect())));
+        _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_files() {
+        let directory = test_directory("evict");
+        let now = SystemTime::now();
+        let file_names = ["rng_0_1.bin", "rng_0_2.bin", "rng_0_3.bin", "rng_0_4.bin", "seeds_0.bin"];
+        for (age, file_name) in file_names.iter().enumerate() {
+            fs::write(directory.join(file_name), [0; 100]).unwrap();
+            let modified = now - std::time::Duration::from_secs(100 * (age as u64 + 1));
+            File::options().write(true).open(directory.join(file_name)).unwrap().set_modified(modified).unwrap();
+        }
+
+        // The oldest file is in use, so the next oldest ones go first (and seed tables are left alone)
+        evict_precomputed_rng(&directory, 250, &directory.join("rng_0_4.bin"));
+        let mut remaining: Vec<String> = fs::read_dir(&directory).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        remaining.sort();
+        assert_eq!(remaining, ["rng_0_1.bin", "rng_0_4.bin", "seeds_0.bin"]);
+        _ = fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,58 @@
+use std::{collections::{HashMap, HashSet}, fs};
+
+use crate::rng::RNG;
+
+// Reads the clock that GameMaker's randomize() derives seeds from: milliseconds since the system started (wrapping)
+pub fn current_seed_clock() -> Option<u32> {
+    if cfg!(windows) {
+        Some(unsafe { windows_sys::Win32::System::SystemInformation::GetTickCount() })
+    } else {
+        let uptime = fs::read_to_string("/proc/uptime").ok()?;
+        let seconds: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+        Some((seconds * 1000.0) as u64 as u32)
+    }
+}
+
+// Orders the unique seeds that could have come from a clock reading near the expected one, most plausible first.
+// Seeds are only included if some clock reading within the tolerance (in milliseconds) maps onto them.
+pub fn prioritize_seeds(unique_seeds: &[u32], seeds_15bit: bool, expected_seed: u32, tolerance: u32) -> Vec<u32> {
+    let unique_seeds_by_state: HashMap<u32, u32> = unique_seeds.iter()
+        .map(|&seed| (RNG::initial_seed_state(seed, seeds_15bit), seed))
+        .collect();
+
+    // Work outwards from the expected seed, in both directions
+    let mut prioritized_seeds: Vec<u32> = Vec::new();
+    let mut included_seeds: HashSet<u32> = HashSet::new();
+    for distance in 0..=tolerance {
+        for seed in [expected_seed.wrapping_add(distance), expected_seed.wrapping_sub(distance)] {
+            let Some(&unique_seed) = unique_seeds_by_state.get(&RNG::initial_seed_state(seed, seeds_15bit)) else {
+                continue;
+            };
+            if included_seeds.insert(unique_seed) {
+                prioritized_seeds.push(unique_seed);
+            }
+        }
+    }
+    prioritized_seeds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prioritizes_nearest_seeds_first() {
+        for seeds_15bit in [false, true] {
+            let unique_seeds = RNG::calculate_unique_seeds(seeds_15bit);
+            let prioritized = prioritize_seeds(&unique_seeds, seeds_15bit, 1_000_000, 50);
+            assert!(!prioritized.is_empty() && prioritized.len() <= 101);
+            assert_eq!(RNG::initial_seed_state(prioritized[0], seeds_15bit), RNG::initial_seed_state(1_000_000, seeds_15bit));
+
+            // Every seed within the tolerance is represented, and nothing is repeated
+            for seed in 999_950..=1_000_050 {
+                let state = RNG::initial_seed_state(seed, seeds_15bit);
+                assert_eq!(prioritized.iter().filter(|&&unique_seed| RNG::initial_seed_state(unique_seed, seeds_15bit) == state).count(), 1);
+            }
+        }
+    }
+}
@@ -10,19 +10,19 @@ use defer_rs::defer;
 
 use crate::config::Config;
 
-const MESSAGE_HEADER_SIZE: usize = 24;
-
-const MESSAGE_CODE_HELLO: u32 = 0x55541000;
-const MESSAGE_CODE_HELLO_ACK: u32 = 0x55541001;
-const MESSAGE_CODE_GOODBYE: u32 = 0x55542000;
-const MESSAGE_CODE_SCREENSHOT: u32 = 0x55544000;
-const MESSAGE_CODE_SCREENSHOT_START_DELAY: u32 = 0x55545000;
-const MESSAGE_CODE_SCREENSHOT_MODE: u32 = 0x55546000;
-const MESSAGE_CODE_HOTKEY_1: u32 = 0x55548000;
-const MESSAGE_CODE_HOTKEY_2: u32 = 0x55548001;
-const MESSAGE_CODE_HOTKEY_3: u32 = 0x55548002;
-const MESSAGE_CODE_HOTKEY_4: u32 = 0x55548003;
-const MESSAGE_CODE_HOTKEY_5: u32 = 0x55548004;
+pub const MESSAGE_HEADER_SIZE: usize = 24;
+
+pub const MESSAGE_CODE_HELLO: u32 = 0x55541000;
+pub const MESSAGE_CODE_HELLO_ACK: u32 = 0x55541001;
+pub const MESSAGE_CODE_GOODBYE: u32 = 0x55542000;
+pub const MESSAGE_CODE_SCREENSHOT: u32 = 0x55544000;
+pub const MESSAGE_CODE_SCREENSHOT_START_DELAY: u32 = 0x55545000;
+pub const MESSAGE_CODE_SCREENSHOT_MODE: u32 = 0x55546000;
+pub const MESSAGE_CODE_HOTKEY_1: u32 = 0x55548000;
+pub const MESSAGE_CODE_HOTKEY_2: u32 = 0x55548001;
+pub const MESSAGE_CODE_HOTKEY_3: u32 = 0x55548002;
+pub const MESSAGE_CODE_HOTKEY_4: u32 = 0x55548003;
+pub const MESSAGE_CODE_HOTKEY_5: u32 = 0x55548004;
 
 #[derive(Clone)]
 pub struct ScreenshotData {
@@ -73,7 +73,7 @@ impl ScreenshotData {
 }
 
 // Message headers received from the OBS plugin
-struct MessageHeader {
+pub struct MessageHeader {
 	pub message_code: u32,
 	pub screenshot_has_more: u32,
 	pub screenshot_width: u32,
@@ -81,6 +81,39 @@ struct MessageHeader {
 	pub screenshot_stride: u32,
 	pub screenshot_bits_per_pixel: u32
 }
+impl MessageHeader {
+    // Header for a message without screenshot data
+    pub fn new(message_code: u32) -> Self {
+        MessageHeader {
+            message_code,
+            screenshot_has_more: 0,
+            screenshot_width: 0,
+            screenshot_height: 0,
+            screenshot_stride: 0,
+            screenshot_bits_per_pixel: 0
+        }
+    }
+    pub fn from_bytes(data: &[u8; MESSAGE_HEADER_SIZE]) -> Self {
+        MessageHeader { 
+            message_code: u32::from_be_bytes(data[0..4].try_into().unwrap()), 
+            screenshot_has_more: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            screenshot_width: u32::from_be_bytes(data[8..12].try_into().unwrap()), 
+            screenshot_height: u32::from_be_bytes(data[12..16].try_into().unwrap()), 
+            screenshot_stride: u32::from_be_bytes(data[16..20].try_into().unwrap()), 
+            screenshot_bits_per_pixel: u32::from_be_bytes(data[20..24].try_into().unwrap()),
+        }
+    }
+    pub fn to_bytes(&self) -> [u8; MESSAGE_HEADER_SIZE] {
+        let mut data = [0; MESSAGE_HEADER_SIZE];
+        data[0..4].copy_from_slice(&self.message_code.to_be_bytes());
+        data[4..8].copy_from_slice(&self.screenshot_has_more.to_be_bytes());
+        data[8..12].copy_from_slice(&self.screenshot_width.to_be_bytes());
+        data[12..16].copy_from_slice(&self.screenshot_height.to_be_bytes());
+        data[16..20].copy_from_slice(&self.screenshot_stride.to_be_bytes());
+        data[20..24].copy_from_slice(&self.screenshot_bits_per_pixel.to_be_bytes());
+        data
+    }
+}
 
 // Messages to be routed to the OBS plugin
 pub struct MessageToSend {
@@ -102,11 +135,134 @@ impl MessageToSend {
     }
 }
 
+// Largest screenshot payload accepted from the OBS plugin; anything bigger means the stream can't be trusted
+// (a 640x480 screenshot is 1.2MB, so this leaves plenty of room for other sizes that get skipped)
+const MAX_SCREENSHOT_PAYLOAD_SIZE: u64 = 16 * 1024 * 1024;
+
+// How much to read from the connection at once
+const RECEIVE_BUFFER_SIZE: usize = 64 * 1024;
+
+// Messages decoded from the OBS plugin's stream
+pub enum PluginMessage {
+    // Any message without data (hello, goodbye, hotkeys, or unknown codes)
+    Control(u32),
+    Screenshot { has_more: bool, screenshot: ScreenshotData },
+    // A screenshot in a format we can't handle, whose data was skipped
+    UnsupportedScreenshot(MessageHeader)
+}
+
+enum DecoderState {
+    Header,
+    Screenshot { header: MessageHeader, data: Vec<u8>, length: usize },
+    SkipScreenshot { header: MessageHeader, remaining: u64 }
+}
+
+// Incrementally decodes messages from the OBS plugin, regardless of how the stream gets split up into reads
+pub struct MessageDecoder {
+    header_data: Vec<u8>,
+    state: DecoderState
+}
+impl MessageDecoder {
+    pub fn new() -> Self {
+        MessageDecoder {
+            header_data: Vec::with_capacity(MESSAGE_HEADER_SIZE),
+            state: DecoderState::Header
+        }
+    }
+
+    // Decodes received data, returning every message it completes. Partial messages are kept until the rest arrives.
+    // After an error, the stream can't be decoded any further.
+    pub fn feed(&mut self, mut data: &[u8]) -> Result<Vec<PluginMessage>, String> {
+        let mut messages = Vec::new();
+        while !data.is_empty() {
+            match &mut self.state {
+                DecoderState::Header => {
+                    let size = (MESSAGE_HEADER_SIZE - self.header_data.len()).min(data.len());
+                    self.header_data.extend_from_slice(&data[..size]);
+                    data = &data[size..];
+                    if self.header_data.len() == MESSAGE_HEADER_SIZE {
+                        let header = MessageHeader::from_bytes(self.header_data[..].try_into().unwrap());
+                        self.header_data.clear();
+                        if let Some(message) = self.begin_message(header)? {
+                            messages.push(message);
+                        }
+                    }
+                },
+                DecoderState::Screenshot { data: screenshot_data, length, .. } => {
+                    let size = (*length - screenshot_data.len()).min(data.len());
+                    screenshot_data.extend_from_slice(&data[..size]);
+                    data = &data[size..];
+                    if screenshot_data.len() == *length {
+                        let DecoderState::Screenshot { header, data: screenshot_data, .. } = std::mem::replace(&mut self.state, DecoderState::Header) else { unreachable!() };
+                        messages.push(PluginMessage::Screenshot {
+                            has_more: header.screenshot_has_more != 0,
+                            screenshot: ScreenshotData {
+                                width: header.screenshot_width,
+                                height: header.screenshot_height,
+                                stride: header.screenshot_stride,
+                                data: screenshot_data
+                            }
+                        });
+                    }
+                },
+                DecoderState::SkipScreenshot { remaining, .. } => {
+                    let size = (*remaining).min(data.len() as u64);
+                    *remaining -= size;
+                    data = &data[size as usize..];
+                    if *remaining == 0 {
+                        let DecoderState::SkipScreenshot { header, .. } = std::mem::replace(&mut self.state, DecoderState::Header) else { unreachable!() };
+                        messages.push(PluginMessage::UnsupportedScreenshot(header));
+                    }
+                }
+            }
+        }
+        Ok(messages)
+    }
+
+    // Starts decoding the message with the given header, returning it right away if it has no data
+    fn begin_message(&mut self, header: MessageHeader) -> Result<Option<PluginMessage>, String> {
+        if header.message_code != MESSAGE_CODE_SCREENSHOT {
+            return Ok(Some(PluginMessage::Control(header.message_code)));
+        }
+
+        let length = header.screenshot_stride as u64 * header.screenshot_height as u64;
+        if length > MAX_SCREENSHOT_PAYLOAD_SIZE {
+            return Err(format!("Received oversized screenshot ({} bytes, maximum is {})", length, MAX_SCREENSHOT_PAYLOAD_SIZE));
+        }
+
+        // Verify that this is an image format we can handle
+        if header.screenshot_bits_per_pixel != 32 ||
+           header.screenshot_width != 640 ||
+           header.screenshot_height != 480 ||
+           header.screenshot_stride < header.screenshot_width * 4 {
+            // Without any data to skip, there's nothing to wait for
+            if length == 0 {
+                return Ok(Some(PluginMessage::UnsupportedScreenshot(header)));
+            }
+            self.state = DecoderState::SkipScreenshot { header, remaining: length };
+        } else {
+            self.state = DecoderState::Screenshot { header, data: Vec::with_capacity(length as usize), length: length as usize };
+        }
+        Ok(None)
+    }
+
+    // Checks that the stream didn't end partway through a message
+    pub fn finish(&self) -> Result<(), String> {
+        match &self.state {
+            DecoderState::Header if self.header_data.is_empty() => Ok(()),
+            DecoderState::Header => Err(format!("Connection closed partway through a message header ({} of {} bytes)", self.header_data.len(), MESSAGE_HEADER_SIZE)),
+            DecoderState::Screenshot { data, length, .. } => Err(format!("Connection closed partway through a screenshot ({} of {} bytes)", data.len(), length)),
+            DecoderState::SkipScreenshot { remaining, .. } => Err(format!("Connection closed partway through a screenshot ({} bytes left)", remaining))
+        }
+    }
+}
+
 pub fn run_server(config: &Config, end_signal: Arc<AtomicBool>, connected: Arc<AtomicBool>, screenshot_data: Arc<Mutex<Vec<ScreenshotData>>>, 
                   hotkey_sender: Sender<u32>, messages_to_send_receiver: Receiver<MessageToSend>) {
     println!("Server thread started");
     let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], config.server_port))).expect("Failed to bind server to port");
     listener.set_nonblocking(true).unwrap();
+    let mut data = vec![0; RECEIVE_BUFFER_SIZE];
     'listener_loop: for stream in listener.incoming() {
         match stream {
             Ok(mut stream) => {
@@ -117,185 +273,121 @@ pub fn run_server(config: &Config, end_signal: Arc<AtomicBool>, connected: Arc<A
                 stream.set_nonblocking(true).unwrap();
 
                 let mut local_screenshot_data: Vec<ScreenshotData> = Vec::with_capacity(100);
-                let mut data = [0 as u8; MESSAGE_HEADER_SIZE];
+                let mut decoder = MessageDecoder::new();
                 let mut got_hello = false;
-                while match stream.read(&mut data) {
-                    Ok(size) => {
-                        if end_signal.load(Ordering::Relaxed) {
-                            stream.shutdown(Shutdown::Both).unwrap();
-                            return;
-                        }
-                        if size == 0 {
-                            println!("Connection closed");
-                            continue 'listener_loop;
-                        }
-                        if size != MESSAGE_HEADER_SIZE {
-                            println!("Received unexpected size ({})", size);
-                            _ = stream.shutdown(Shutdown::Both);
-                            continue 'listener_loop;
-                        }
+                loop {
+                    match stream.read(&mut data) {
+                        Ok(size) => {
+                            if end_signal.load(Ordering::Relaxed) {
+                                _ = stream.shutdown(Shutdown::Both);
+                                return;
+                            }
+                            if size == 0 {
+                                if let Err(e) = decoder.finish() {
+                                    println!("{}", e);
+                                }
+                                println!("Connection closed");
+                                continue 'listener_loop;
+                            }
 
-                        let header: MessageHeader = MessageHeader { 
-                            message_code: u32::from_be_bytes(data[0..4].try_into().unwrap()), 
-                            screenshot_has_more: u32::from_be_bytes(data[4..8].try_into().unwrap()),
-                            screenshot_width: u32::from_be_bytes(data[8..12].try_into().unwrap()), 
-                            screenshot_height: u32::from_be_bytes(data[12..16].try_into().unwrap()), 
-                            screenshot_stride: u32::from_be_bytes(data[16..20].try_into().unwrap()), 
-                            screenshot_bits_per_pixel: u32::from_be_bytes(data[20..24].try_into().unwrap()),
-                        };
-
-                        match header.message_code {
-                            MESSAGE_CODE_HELLO => {
-                                println!("Received hello message, sending acknowledgment back");
-                                connected.store(true, Ordering::Relaxed);
-                                got_hello = true;
-                                if let Err(e) = stream.write(&u32::to_be_bytes(MESSAGE_CODE_HELLO_ACK)) {
-                                    println!("An error occurred, terminating connection with {}", stream.peer_addr().unwrap());
-                                    println!("Error: {e}");
+                            let messages = match decoder.feed(&data[..size]) {
+                                Ok(messages) => messages,
+                                Err(e) => {
+                                    println!("{}, terminating connection", e);
                                     _ = stream.shutdown(Shutdown::Both);
-                                }
-                            },
-                            MESSAGE_CODE_GOODBYE => {
-                                println!("Received goodbye message, disconnecting");
-                                connected.store(false, Ordering::Relaxed);
-                                _ = stream.shutdown(Shutdown::Both);
-                            },
-                            MESSAGE_CODE_HOTKEY_1 => {
-                                _ = hotkey_sender.send(0);
-                            },
-                            MESSAGE_CODE_HOTKEY_2 => {
-                                _ = hotkey_sender.send(1);
-                            },
-                            MESSAGE_CODE_HOTKEY_3 => {
-                                _ = hotkey_sender.send(2);
-                            },
-                            MESSAGE_CODE_HOTKEY_4 => {
-                                _ = hotkey_sender.send(3);
-                            },
-                            MESSAGE_CODE_HOTKEY_5 => {
-                                _ = hotkey_sender.send(4);
-                            },
-                            MESSAGE_CODE_SCREENSHOT => {
-                                if !got_hello {
-                                    println!("Received unexpected message");
-                                    stream.shutdown(Shutdown::Both).unwrap();
                                     continue 'listener_loop;
                                 }
-
-                                // Verify that this is an image format we can handle
-                                if header.screenshot_bits_per_pixel != 32 ||
-                                   header.screenshot_width != 640 ||
-                                   header.screenshot_height != 480 {
-                                    println!("Received unexpected screenshot params, ignoring");
-                                    match stream.read_to_end(&mut Vec::new()) {
-                                        Ok(_) => {},
-                                        Err(e) => {
+                            };
+                            for message in messages {
+                                match message {
+                                    PluginMessage::Control(MESSAGE_CODE_HELLO) => {
+                                        println!("Received hello message, sending acknowledgment back");
+                                        connected.store(true, Ordering::Relaxed);
+                                        got_hello = true;
+                                        if let Err(e) = stream.write_all(&u32::to_be_bytes(MESSAGE_CODE_HELLO_ACK)) {
                                             println!("An error occurred, terminating connection with {}", stream.peer_addr().unwrap());
                                             println!("Error: {e}");
                                             _ = stream.shutdown(Shutdown::Both);
+                                            continue 'listener_loop;
+                                        }
+                                    },
+                                    PluginMessage::Control(MESSAGE_CODE_GOODBYE) => {
+                                        println!("Received goodbye message, disconnecting");
+                                        connected.store(false, Ordering::Relaxed);
+                                        _ = stream.shutdown(Shutdown::Both);
+                                        continue 'listener_loop;
+                                    },
+                                    PluginMessage::Control(MESSAGE_CODE_HOTKEY_1) => {
+                                        _ = hotkey_sender.send(0);
+                                    },
+                                    PluginMessage::Control(MESSAGE_CODE_HOTKEY_2) => {
+                                        _ = hotkey_sender.send(1);
+                                    },
+                                    PluginMessage::Control(MESSAGE_CODE_HOTKEY_3) => {
+                                        _ = hotkey_sender.send(2);
+                                    },
+                                    PluginMessage::Control(MESSAGE_CODE_HOTKEY_4) => {
+                                        _ = hotkey_sender.send(3);
+                                    },
+                                    PluginMessage::Control(MESSAGE_CODE_HOTKEY_5) => {
+                                        _ = hotkey_sender.send(4);
+                                    },
+                                    _ if !got_hello => {
+                                        println!("Received unexpected message");
+                                        _ = stream.shutdown(Shutdown::Both);
+                                        continue 'listener_loop;
+                                    },
+                                    PluginMessage::Control(_) => {
+                                        println!("Unknown message code");
+                                    },
+                                    PluginMessage::UnsupportedScreenshot(header) => {
+                                        println!("Received unexpected screenshot params ({}x{}, {} bits per pixel, stride {}), ignoring",
+                                                 header.screenshot_width, header.screenshot_height, header.screenshot_bits_per_pixel, header.screenshot_stride);
+                                    },
+                                    PluginMessage::Screenshot { has_more, screenshot } => {
+                                        // If the screenshot data isn't a duplicate of the previous screenshot, add it to the vector
+                                        if local_screenshot_data.len() == 0 || !screenshot.is_same_as(local_screenshot_data.last().unwrap()) {
+                                            local_screenshot_data.push(screenshot);
                                         }
-                                    }
-                                    continue 'listener_loop;
-                                }
 
-                                // Read all of the screenshot data
-                                let screenshot_length = (header.screenshot_stride * header.screenshot_height) as usize;
-                                let mut recv_screenshot_data: Vec<u8> = vec![0; screenshot_length];
-                                let mut recv_data_pos = 0;
-                                let mut recv_data_remaining = screenshot_length;
-
-                                while recv_data_remaining > 0 {
-                                    match stream.read(&mut recv_screenshot_data[recv_data_pos..]) {
-                                        Ok(size) => {
-                                            if size == 0 {
-                                                println!("Connection closed");
-                                                continue 'listener_loop;
-                                            }
-                                            if size > recv_data_remaining {
-                                                println!("Received unexpected extra screenshot data, ignoring (received {} instead of maximum {})", size, recv_data_remaining);
-                                                continue 'listener_loop;
-                                            }
-
-                                            recv_data_pos += size;
-                                            recv_data_remaining -= size;
-
-                                            if recv_data_remaining == 0 {
-                                                let new_screenshot_data = ScreenshotData { 
-                                                    width: header.screenshot_width, 
-                                                    height: header.screenshot_height, 
-                                                    stride: header.screenshot_stride, 
-                                                    data: recv_screenshot_data 
-                                                };
-
-                                                // If the screenshot data isn't a duplicate of the previous screenshot, add it to the vector
-                                                if local_screenshot_data.len() == 0 || !new_screenshot_data.is_same_as(local_screenshot_data.last().unwrap()) {
-                                                    local_screenshot_data.push(new_screenshot_data);
-                                                }
-    
-                                                if header.screenshot_has_more == 0 {
-                                                    // Flush local screenshots to main thread
-                                                    screenshot_data.lock().unwrap().append(&mut local_screenshot_data);
-                                                }
-                                                break;
-                                            }
-                                        },
-                                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                                            if end_signal.load(Ordering::Relaxed) {
-                                                _ = stream.shutdown(Shutdown::Both);
-                                                return;
-                                            }
-                                            thread::sleep(Duration::from_millis(5));
-                                        },
-                                        Err(e) => {
-                                            println!("An error occurred, terminating connection with {}", stream.peer_addr().unwrap());
-                                            println!("Error: {e}");
-                                            _ = stream.shutdown(Shutdown::Both);
+                                        if !has_more {
+                                            // Flush local screenshots to main thread
+                                            screenshot_data.lock().unwrap().append(&mut local_screenshot_data);
                                         }
                                     }
-                                    thread::sleep(Duration::from_millis(5));
-                                }
-                            },
-                            _ => {
-                                if !got_hello {
-                                    println!("Received unexpected message");
-                                    _ = stream.shutdown(Shutdown::Both);
-                                    continue 'listener_loop;
                                 }
-                                println!("Unknown message code");
                             }
-                        }
-
-                        true
-                    },
-                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                        // Shut down server if the signal was received
-                        if end_signal.load(Ordering::Relaxed) {
-                            _ = stream.shutdown(Shutdown::Both);
-                            return;
-                        }
+                        },
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            // Shut down server if the signal was received
+                            if end_signal.load(Ordering::Relaxed) {
+                                _ = stream.shutdown(Shutdown::Both);
+                                return;
+                            }
 
-                        // Send any pending messages
-                        if got_hello {
-                            for message_to_send in messages_to_send_receiver.try_iter() {
-                                if let Err(e) = stream.write(&[u32::to_be_bytes(message_to_send.message_code), u32::to_be_bytes(message_to_send.data)].concat()) {
-                                    println!("An error occurred, terminating connection with {}", stream.peer_addr().unwrap());
-                                    println!("Error: {e}");
-                                    _ = stream.shutdown(Shutdown::Both);
+                            // Send any pending messages
+                            if got_hello {
+                                for message_to_send in messages_to_send_receiver.try_iter() {
+                                    if let Err(e) = stream.write_all(&[u32::to_be_bytes(message_to_send.message_code), u32::to_be_bytes(message_to_send.data)].concat()) {
+                                        println!("An error occurred, terminating connection with {}", stream.peer_addr().unwrap());
+                                        println!("Error: {e}");
+                                        _ = stream.shutdown(Shutdown::Both);
+                                        continue 'listener_loop;
+                                    }
                                 }
                             }
-                        }
 
-                        // Nothing to do; sleep for a bit to not waste CPU
-                        thread::sleep(Duration::from_millis(10));
-                        true
-                    },
-                    Err(e) => {
-                        println!("An error occurred, terminating connection with {}", stream.peer_addr().unwrap());
-                        println!("Error: {e}");
-                        _ = stream.shutdown(Shutdown::Both);
-                        false
+                            // Nothing to do; sleep for a bit to not waste CPU
+                            thread::sleep(Duration::from_millis(10));
+                        },
+                        Err(e) => {
+                            println!("An error occurred, terminating connection with {}", stream.peer_addr().unwrap());
+                            println!("Error: {e}");
+                            _ = stream.shutdown(Shutdown::Both);
+                            continue 'listener_loop;
+                        }
                     }
-                } {}
+                }
             },
             Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
                 // Shut down server if the signal was received
@@ -311,4 +403,114 @@ pub fn run_server(config: &Config, end_signal: Arc<AtomicBool>, connected: Arc<A
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screenshot_message(has_more: bool, width: u32, height: u32, fill: u8) -> Vec<u8> {
+        let header = MessageHeader {
+            message_code: MESSAGE_CODE_SCREENSHOT,
+            screenshot_has_more: if has_more { 1 } else { 0 },
+            screenshot_width: width,
+            screenshot_height: height,
+            screenshot_stride: width * 4,
+            screenshot_bits_per_pixel: 32
+        };
+        [&header.to_bytes()[..], &vec![fill; (width * 4 * height) as usize]].concat()
+    }
+
+    fn test_stream() -> Vec<u8> {
+        [
+            &MessageHeader::new(MESSAGE_CODE_HELLO).to_bytes()[..],
+            &screenshot_message(true, 640, 480, 0x10),
+            &screenshot_message(false, 640, 480, 0x20),
+            &MessageHeader::new(MESSAGE_CODE_HOTKEY_2).to_bytes()
+        ].concat()
+    }
+
+    fn check_test_messages(messages: &[PluginMessage]) {
+        assert_eq!(messages.len(), 4);
+        assert!(matches!(messages[0], PluginMessage::Control(MESSAGE_CODE_HELLO)));
+        assert!(matches!(&messages[1], PluginMessage::Screenshot { has_more: true, screenshot } if screenshot.data.len() == 640 * 4 * 480 && screenshot.data[0] == 0x10));
+        assert!(matches!(&messages[2], PluginMessage::Screenshot { has_more: false, screenshot } if screenshot.data.iter().all(|&value| value == 0x20)));
+        assert!(matches!(messages[3], PluginMessage::Control(MESSAGE_CODE_HOTKEY_2)));
+    }
+
+    #[test]
+    fn decodes_fragmented_and_coalesced_messages() {
+        let stream = test_stream();
+
+        // Everything in a single read
+        let mut decoder = MessageDecoder::new();
+        check_test_messages(&decoder.feed(&stream).unwrap());
+        assert!(decoder.finish().is_ok());
+
+        // Reads that split headers and screenshot data at arbitrary points (only byte by byte for the start, to keep this quick)
+        for (chunk_size, chunked_length) in [(1, 2000), (7, stream.len()), (23, stream.len()), (25, stream.len()), (4096, stream.len())] {
+            let mut decoder = MessageDecoder::new();
+            let mut messages = Vec::new();
+            let (start, rest) = stream.split_at(chunked_length);
+            for chunk in start.chunks(chunk_size) {
+                messages.extend(decoder.feed(chunk).unwrap());
+            }
+            messages.extend(decoder.feed(rest).unwrap());
+            check_test_messages(&messages);
+            assert!(decoder.finish().is_ok());
+        }
+    }
+
+    #[test]
+    fn skips_unsupported_and_rejects_oversized_screenshots() {
+        let mut decoder = MessageDecoder::new();
+        let stream = [&screenshot_message(false, 320, 240, 0)[..], &MessageHeader::new(MESSAGE_CODE_HOTKEY_1).to_bytes()].concat();
+        let messages = decoder.feed(&stream).unwrap();
+        assert!(matches!(&messages[..], [PluginMessage::UnsupportedScreenshot(header), PluginMessage::Control(MESSAGE_CODE_HOTKEY_1)] if header.screenshot_width == 320));
+
+        // Zero-length screenshots have no data to skip at all
+        let empty_header = MessageHeader {
+            message_code: MESSAGE_CODE_SCREENSHOT,
+            screenshot_has_more: 0,
+            screenshot_width: 640,
+            screenshot_height: 0,
+            screenshot_stride: 2560,
+            screenshot_bits_per_pixel: 32
+        };
+        let messages = decoder.feed(&empty_header.to_bytes()).unwrap();
+        assert!(matches!(&messages[..], [PluginMessage::UnsupportedScreenshot(header)] if header.screenshot_height == 0));
+        assert!(decoder.finish().is_ok());
+
+        let header = MessageHeader {
+            message_code: MESSAGE_CODE_SCREENSHOT,
+            screenshot_has_more: 0,
+            screenshot_width: 640,
+            screenshot_height: 480,
+            screenshot_stride: u32::MAX,
+            screenshot_bits_per_pixel: 32
+        };
+        assert!(MessageDecoder::new().feed(&header.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn reports_early_close() {
+        let stream = test_stream();
+        let header_size = MESSAGE_HEADER_SIZE;
+
+        let mut decoder = MessageDecoder::new();
+        decoder.feed(&stream[..header_size + 10]).unwrap();
+        assert!(decoder.finish().is_err());
+
+        let mut decoder = MessageDecoder::new();
+        decoder.feed(&stream[..header_size * 2 + 1000]).unwrap();
+        assert!(decoder.finish().is_err());
+
+        let mut decoder = MessageDecoder::new();
+        decoder.feed(&screenshot_message(false, 320, 240, 0)[..header_size + 1]).unwrap();
+        assert!(decoder.finish().is_err());
+
+        let mut decoder = MessageDecoder::new();
+        assert_eq!(decoder.feed(&stream[..header_size]).unwrap().len(), 1);
+        assert!(decoder.finish().is_ok());
+    }
+}
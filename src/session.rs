@@ -0,0 +1,502 @@
+use std::{collections::VecDeque, fs::{self, File}, io::{BufWriter, Cursor, Write}, path::Path, time::Instant};
+
+use image::{ImageFormat, RgbaImage};
+use sdl3::{event::Event, keyboard::{Keycode, Mod, Scancode}, mouse::{MouseButton, MouseState}};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, program_common::ScreenSpace, server::ScreenshotData};
+
+// Increase whenever the file layout (or any recorded event) changes. Older session files can't be replayed after that.
+const SESSION_FORMAT_VERSION: u32 = 2;
+
+const SESSION_MAGIC: &[u8; 4] = b"DMSN";
+const SESSION_HEADER_SIZE: usize = 8;
+const RECORD_HEADER_SIZE: usize = 13;
+
+const RECORD_KIND_EVENT: u8 = 0;
+const RECORD_KIND_SCREENSHOT: u8 = 1;
+
+// Input event delivered to a program, in a form that can be saved (only the kinds of events that programs respond to).
+// Mouse positions are relative to the tool's screen area (in DEFAULT_SCREEN_WIDTH x DEFAULT_SCREEN_HEIGHT units), rather than
+// the window, so that they land on the same spots when replayed with a different window size.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum InputEvent {
+    Quit,
+    KeyDown { keycode: Option<i32>, scancode: Option<i32>, keymod: u16, repeat: bool },
+    KeyUp { keycode: Option<i32>, scancode: Option<i32>, keymod: u16, repeat: bool },
+    MouseMotion { mousestate: u32, x: f32, y: f32, xrel: f32, yrel: f32 },
+    MouseButtonDown { button: u8, clicks: u8, x: f32, y: f32 },
+    MouseButtonUp { button: u8, clicks: u8, x: f32, y: f32 }
+}
+impl InputEvent {
+    pub fn from_sdl(event: &Event, screen_space: &ScreenSpace) -> Option<Self> {
+        let to_screen = |x: f32, y: f32| window_to_screen(x, y, screen_space);
+        Some(match *event {
+            Event::Quit { .. } => Self::Quit,
+            Event::KeyDown { keycode, scancode, keymod, repeat, .. } => Self::KeyDown {
+                keycode: keycode.map(|keycode| keycode as i32),
+                scancode: scancode.map(|scancode| scancode as i32),
+                keymod: keymod.bits(),
+                repeat
+            },
+            Event::KeyUp { keycode, scancode, keymod, repeat, .. } => Self::KeyUp {
+                keycode: keycode.map(|keycode| keycode as i32),
+                scancode: scancode.map(|scancode| scancode as i32),
+                keymod: keymod.bits(),
+                repeat
+            },
+            Event::MouseMotion { mousestate, x, y, xrel, yrel, .. } => {
+                let (x, y) = to_screen(x, y);
+                Self::MouseMotion { mousestate: mousestate.to_sdl_state(), x, y, xrel: xrel / screen_space.scale(), yrel: yrel / screen_space.scale() }
+            },
+            Event::MouseButtonDown { mouse_btn, clicks, x, y, .. } => {
+                let (x, y) = to_screen(x, y);
+                Self::MouseButtonDown { button: mouse_btn as u8, clicks, x, y }
+            },
+            Event::MouseButtonUp { mouse_btn, clicks, x, y, .. } => {
+                let (x, y) = to_screen(x, y);
+                Self::MouseButtonUp { button: mouse_btn as u8, clicks, x, y }
+            },
+            _ => return None
+        })
+    }
+
+    // Converts back into an SDL event, as if it was just received by the given window
+    pub fn to_sdl(&self, window_id: u32, screen_space: &ScreenSpace) -> Event {
+        let to_window = |x: f32, y: f32| screen_to_window(x, y, screen_space);
+        match *self {
+            Self::Quit => Event::Quit { timestamp: 0 },
+            Self::KeyDown { keycode, scancode, keymod, repeat } => Event::KeyDown {
+                timestamp: 0,
+                window_id,
+                keycode: keycode.and_then(Keycode::from_i32),
+                scancode: scancode.and_then(Scancode::from_i32),
+                keymod: Mod::from_bits_truncate(keymod),
+                repeat,
+                which: 0,
+                raw: 0
+            },
+            Self::KeyUp { keycode, scancode, keymod, repeat } => Event::KeyUp {
+                timestamp: 0,
+                window_id,
+                keycode: keycode.and_then(Keycode::from_i32),
+                scancode: scancode.and_then(Scancode::from_i32),
+                keymod: Mod::from_bits_truncate(keymod),
+                repeat,
+                which: 0,
+                raw: 0
+            },
+            Self::MouseMotion { mousestate, x, y, xrel, yrel } => {
+                let (x, y) = to_window(x, y);
+                Event::MouseMotion {
+                    timestamp: 0,
+                    window_id,
+                    which: 0,
+                    mousestate: MouseState::from_sdl_state(mousestate),
+                    x, y,
+                    xrel: xrel * screen_space.scale(),
+                    yrel: yrel * screen_space.scale()
+                }
+            },
+            Self::MouseButtonDown { button, clicks, x, y } => {
+                let (x, y) = to_window(x, y);
+                Event::MouseButtonDown {
+                    timestamp: 0,
+                    window_id,
+                    which: 0,
+                    mouse_btn: MouseButton::from_ll(button),
+                    clicks, x, y
+                }
+            },
+            Self::MouseButtonUp { button, clicks, x, y } => {
+                let (x, y) = to_window(x, y);
+                Event::MouseButtonUp {
+                    timestamp: 0,
+                    window_id,
+                    which: 0,
+                    mouse_btn: MouseButton::from_ll(button),
+                    clicks, x, y
+                }
+            }
+        }
+    }
+}
+
+fn window_to_screen(x: f32, y: f32, screen_space: &ScreenSpace) -> (f32, f32) {
+    let screen_rect = screen_space.rect();
+    ((x - screen_rect.x) / screen_space.scale(), (y - screen_rect.y) / screen_space.scale())
+}
+
+fn screen_to_window(x: f32, y: f32, screen_space: &ScreenSpace) -> (f32, f32) {
+    let screen_rect = screen_space.rect();
+    (screen_rect.x + (x * screen_space.scale()), screen_rect.y + (y * screen_space.scale()))
+}
+
+// Change made to the run context (these get recorded to check that a replay ends up the same way)
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum RunTransition {
+    SetRng { rng_seed: u32, min_rng_position: usize },
+    SetMinRngPosition(usize),
+    SetLaunchSeedClock(u32),
+    Reset
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum SessionEvent {
+    // Config in use, at the start of the session and whenever it changes
+    Config { profile: Option<String>, config: serde_json::Value },
+    // Hotkey received from the OBS plugin
+    Hotkey(u32),
+    Input(InputEvent),
+    // Reading of the seed clock (used for capturing launch times)
+    SeedClock(Option<u32>),
+    // Text read from the clipboard (used for pasting RNG outputs)
+    ClipboardText(Option<String>),
+    // A program received the result of a search (which can take a different amount of time on each run)
+    SearchFinished,
+    RunContext(RunTransition)
+}
+impl SessionEvent {
+    pub fn from_config(config: &Config) -> Self {
+        SessionEvent::Config {
+            profile: config.active_profile.clone(),
+            config: serde_json::to_value(config).expect("Failed to serialize config")
+        }
+    }
+}
+
+// Config from a recorded config change
+pub fn config_from_event(profile: Option<String>, config: serde_json::Value) -> Result<Config, String> {
+    let mut config: Config = serde_json::from_value(config).map_err(|e| format!("invalid recorded config ({})", e))?;
+    config.active_profile = profile;
+    Ok(config)
+}
+
+// Everything delivered to programs during a session, other than input events, goes through one of these
+pub enum SessionRecord {
+    Event(SessionEvent),
+    Screenshot(ScreenshotData)
+}
+
+// Record along with when it happened: the frame (counted across all programs) and milliseconds since the session started
+struct TimedRecord {
+    frame: u32,
+    time_ms: u32,
+    record: SessionRecord
+}
+
+// Screenshots are stored as PNGs, to keep session files reasonably small
+fn encode_screenshot(screenshot: &ScreenshotData) -> Result<Vec<u8>, String> {
+    let row_size = (screenshot.width * 4) as usize;
+    let mut pixels: Vec<u8> = Vec::with_capacity(row_size * screenshot.height as usize);
+    for y in 0..screenshot.height as usize {
+        let row_start = y * screenshot.stride as usize;
+        pixels.extend_from_slice(&screenshot.data[row_start..(row_start + row_size)]);
+    }
+    let image = RgbaImage::from_raw(screenshot.width, screenshot.height, pixels).ok_or("screenshot data is too small")?;
+    let mut png: Vec<u8> = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+fn decode_screenshot(png: &[u8]) -> Result<ScreenshotData, &'static str> {
+    let image = image::load_from_memory_with_format(png, ImageFormat::Png).map_err(|_| "invalid screenshot")?.to_rgba8();
+    Ok(ScreenshotData {
+        width: image.width(),
+        height: image.height(),
+        stride: image.width() * 4,
+        data: image.into_raw()
+    })
+}
+
+fn encode_record(record: &TimedRecord) -> Result<Vec<u8>, String> {
+    let (kind, payload) = match &record.record {
+        SessionRecord::Event(event) => (RECORD_KIND_EVENT, serde_json::to_vec(event).map_err(|e| e.to_string())?),
+        SessionRecord::Screenshot(screenshot) => (RECORD_KIND_SCREENSHOT, encode_screenshot(screenshot)?)
+    };
+    let mut bytes: Vec<u8> = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(&record.frame.to_le_bytes());
+    bytes.extend_from_slice(&record.time_ms.to_le_bytes());
+    bytes.push(kind);
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+// Decodes all records in a session file. A record cut off at the end (e.g. if the tool crashed while recording) is ignored.
+fn decode_records(bytes: &[u8]) -> Result<Vec<TimedRecord>, &'static str> {
+    if bytes.len() < SESSION_HEADER_SIZE || &bytes[0..4] != SESSION_MAGIC {
+        return Err("not a session file");
+    }
+    let read_u32 = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    if read_u32(4) != SESSION_FORMAT_VERSION {
+        return Err("recorded with a different version of the tool");
+    }
+
+    let mut records: Vec<TimedRecord> = Vec::new();
+    let mut pos = SESSION_HEADER_SIZE;
+    while pos + RECORD_HEADER_SIZE <= bytes.len() {
+        let payload_start = pos + RECORD_HEADER_SIZE;
+        let payload_end = payload_start + read_u32(pos + 9) as usize;
+        if payload_end > bytes.len() {
+            break;
+        }
+        let payload = &bytes[payload_start..payload_end];
+        let record = match bytes[pos + 8] {
+            RECORD_KIND_EVENT => SessionRecord::Event(serde_json::from_slice(payload).map_err(|_| "invalid event")?),
+            RECORD_KIND_SCREENSHOT => SessionRecord::Screenshot(decode_screenshot(payload)?),
+            _ => return Err("unknown record kind")
+        };
+        records.push(TimedRecord {
+            frame: read_u32(pos),
+            time_ms: read_u32(pos + 4),
+            record
+        });
+        pos = payload_end;
+    }
+    Ok(records)
+}
+
+// Writes records to a session file as they happen
+pub struct SessionRecorder {
+    writer: BufWriter<File>,
+    start: Instant,
+    failed: bool
+}
+impl SessionRecorder {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let mut writer = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+        writer.write_all(SESSION_MAGIC).and_then(|_| writer.write_all(&SESSION_FORMAT_VERSION.to_le_bytes())).map_err(|e| e.to_string())?;
+        Ok(SessionRecorder {
+            writer,
+            start: Instant::now(),
+            failed: false
+        })
+    }
+
+    pub fn record(&mut self, frame: u32, record: SessionRecord) {
+        if self.failed {
+            return;
+        }
+        let record = TimedRecord {
+            frame,
+            time_ms: self.start.elapsed().as_millis() as u32,
+            record
+        };
+        if let Err(e) = encode_record(&record).and_then(|bytes| self.writer.write_all(&bytes).map_err(|e| e.to_string())) {
+            println!("Failed to record session, so recording has stopped: {}", e);
+            self.failed = true;
+        }
+    }
+
+    // Writes out everything recorded so far, so that it's kept even if the tool crashes
+    pub fn flush(&mut self) {
+        _ = self.writer.flush();
+    }
+}
+
+// Feeds records from a session file back, frame by frame
+pub struct SessionReplay {
+    records: VecDeque<TimedRecord>,
+    // Seed clock readings, clipboard text and run context changes aren't delivered at a specific frame, but in the same order as they were recorded
+    seed_clocks: VecDeque<Option<u32>>,
+    clipboard_texts: VecDeque<Option<String>>,
+    transitions: VecDeque<RunTransition>,
+    // Frame of the session reached so far, which stays put while waiting for a search to finish
+    frame: u32,
+    // Whether a program checked for search results since the last frame
+    search_polled: bool
+}
+impl SessionReplay {
+    // Opens a session file for replaying, returning the config it was recorded with
+    pub fn open(path: &Path) -> Result<(Self, Config), String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let mut replay = SessionReplay::new(decode_records(&bytes)?);
+        let Some(TimedRecord { record: SessionRecord::Event(SessionEvent::Config { profile, config }), .. }) = replay.records.pop_front() else {
+            return Err("session doesn't start with a config".to_string());
+        };
+        let config = config_from_event(profile, config)?;
+        Ok((replay, config))
+    }
+
+    fn new(records: Vec<TimedRecord>) -> Self {
+        let mut replay = SessionReplay {
+            records: VecDeque::new(),
+            seed_clocks: VecDeque::new(),
+            clipboard_texts: VecDeque::new(),
+            transitions: VecDeque::new(),
+            frame: 0,
+            search_polled: false
+        };
+        for record in records {
+            match record.record {
+                SessionRecord::Event(SessionEvent::SeedClock(clock)) => replay.seed_clocks.push_back(clock),
+                SessionRecord::Event(SessionEvent::ClipboardText(text)) => replay.clipboard_texts.push_back(text),
+                SessionRecord::Event(SessionEvent::RunContext(transition)) => replay.transitions.push_back(transition),
+                _ => replay.records.push_back(record)
+            }
+        }
+        replay
+    }
+
+    // Advances to the next frame (unless waiting for a search to finish), taking every record up to it.
+    // Records after a search result are held back until the program receives that result (see take_search_result).
+    pub fn next_frame(&mut self) -> Vec<SessionRecord> {
+        if !self.search_result_due() {
+            self.frame += 1;
+        }
+        let mut records: Vec<SessionRecord> = Vec::new();
+        while self.records.front().is_some_and(|record| record.frame <= self.frame && !matches!(record.record, SessionRecord::Event(SessionEvent::SearchFinished))) {
+            records.push(self.records.pop_front().unwrap().record);
+        }
+        records
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    // Whether a program received a search result at this point of the session
+    pub fn search_result_due(&self) -> bool {
+        self.records.front().is_some_and(|record| record.frame <= self.frame && matches!(record.record, SessionRecord::Event(SessionEvent::SearchFinished)))
+    }
+
+    // Notes that a program is checking for search results this frame
+    pub fn poll_search(&mut self) {
+        self.search_polled = true;
+    }
+
+    // Moves past a search result that's due, once the program has received it
+    pub fn take_search_result(&mut self) {
+        if self.search_result_due() {
+            self.records.pop_front();
+        }
+    }
+
+    // Gives up on a search result that's due, if no program checked for search results during the last frame (meaning no
+    // search is running to produce it). Returns whether that happened.
+    pub fn abandon_unpolled_search(&mut self) -> bool {
+        let abandoned = self.search_result_due() && !self.search_polled;
+        if abandoned {
+            self.records.pop_front();
+        }
+        self.search_polled = false;
+        abandoned
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn next_seed_clock(&mut self) -> Option<u32> {
+        self.seed_clocks.pop_front().flatten()
+    }
+
+    pub fn next_clipboard_text(&mut self) -> Option<String> {
+        self.clipboard_texts.pop_front().flatten()
+    }
+
+    // Compares a change to the run context with the next one that was recorded, describing the difference if they don't match
+    pub fn check_transition(&mut self, transition: &RunTransition) -> Option<String> {
+        match self.transitions.pop_front() {
+            Some(expected) if expected == *transition => None,
+            Some(expected) => Some(format!("expected {:?}, but got {:?}", expected, transition)),
+            None => Some(format!("got {:?}, which wasn't recorded", transition))
+        }
+    }
+}
+
+pub enum Session {
+    None,
+    Recording(SessionRecorder),
+    Replaying(SessionReplay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_session(records: &[TimedRecord]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(SESSION_MAGIC);
+        bytes.extend_from_slice(&SESSION_FORMAT_VERSION.to_le_bytes());
+        for record in records {
+            bytes.extend(encode_record(record).unwrap());
+        }
+        bytes
+    }
+
+    #[test]
+    fn round_trips_records() {
+        // Screenshot with padding at the end of each row, as the plugin can send
+        let screenshot = ScreenshotData {
+            width: 4,
+            height: 3,
+            stride: 20,
+            data: (0..60).map(|i: u32| (i * 7) as u8).collect()
+        };
+        let records = [
+            TimedRecord { frame: 1, time_ms: 10, record: SessionRecord::Event(SessionEvent::Hotkey(2)) },
+            TimedRecord { frame: 5, time_ms: 150, record: SessionRecord::Screenshot(screenshot) },
+            TimedRecord { frame: 6, time_ms: 180, record: SessionRecord::Event(SessionEvent::Input(InputEvent::MouseButtonDown { button: 1, clicks: 1, x: 12.5, y: 40.0 })) }
+        ];
+        let decoded = decode_records(&encode_session(&records)).unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert!(matches!(decoded[0].record, SessionRecord::Event(SessionEvent::Hotkey(2))));
+        assert_eq!((decoded[1].frame, decoded[1].time_ms), (5, 150));
+        let SessionRecord::Screenshot(screenshot) = &decoded[1].record else { panic!("expected a screenshot") };
+        assert_eq!((screenshot.width, screenshot.height, screenshot.stride), (4, 3, 16));
+        for y in 0..3 {
+            let expected: Vec<u8> = (0..16).map(|x: u32| (((y * 20) + x) * 7) as u8).collect();
+            assert_eq!(&screenshot.data[(y * 16) as usize..((y + 1) * 16) as usize], &expected[..]);
+        }
+        assert!(matches!(decoded[2].record, SessionRecord::Event(SessionEvent::Input(InputEvent::MouseButtonDown { button: 1, .. }))));
+    }
+
+    #[test]
+    fn waits_for_search_results() {
+        let event = |frame: u32, event: SessionEvent| TimedRecord { frame, time_ms: 0, record: SessionRecord::Event(event) };
+        let mut replay = SessionReplay::new(vec![
+            event(1, SessionEvent::Hotkey(0)),
+            event(2, SessionEvent::ClipboardText(Some("123".to_string()))),
+            event(2, SessionEvent::SearchFinished),
+            event(3, SessionEvent::Hotkey(1)),
+            event(5, SessionEvent::SearchFinished)
+        ]);
+        assert_eq!(replay.next_frame().len(), 1);
+        assert!(!replay.search_result_due());
+        assert!(replay.next_frame().is_empty());
+        assert!(replay.search_result_due());
+        assert_eq!(replay.next_clipboard_text().as_deref(), Some("123"));
+
+        // The search takes longer than it did when recording, so the replay waits for it
+        replay.poll_search();
+        assert!(!replay.abandon_unpolled_search());
+        assert!(replay.next_frame().is_empty());
+        assert_eq!(replay.frame(), 2);
+        replay.take_search_result();
+        assert!(matches!(replay.next_frame()[..], [SessionRecord::Event(SessionEvent::Hotkey(1))]));
+        assert_eq!(replay.frame(), 3);
+
+        // Nothing is searching when a result is due, so it gets skipped
+        replay.next_frame();
+        replay.next_frame();
+        assert!(replay.search_result_due());
+        assert!(replay.abandon_unpolled_search());
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn ignores_cut_off_records() {
+        let records = [
+            TimedRecord { frame: 1, time_ms: 0, record: SessionRecord::Event(SessionEvent::Hotkey(0)) },
+            TimedRecord { frame: 2, time_ms: 0, record: SessionRecord::Event(SessionEvent::SeedClock(Some(1234))) }
+        ];
+        let bytes = encode_session(&records);
+        assert_eq!(decode_records(&bytes[..bytes.len() - 3]).unwrap().len(), 1);
+
+        let mut outdated = bytes.clone();
+        outdated[4..8].copy_from_slice(&(SESSION_FORMAT_VERSION + 1).to_le_bytes());
+        assert!(decode_records(&outdated).is_err());
+    }
+}
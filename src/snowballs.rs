@@ -63,7 +63,8 @@ impl Snowball {
     }
     pub fn update(&mut self, mainchara_bbox: &BoundingBox, rng: &mut impl LinearRNG) {
         if self.colliding_with(mainchara_bbox) {
-            self.move_amount = f32::floor(rng.next_f64(4.0) as f32) as i32 + 2;
+            // Floored in single precision, unlike irandom(3): values just under 4.0 round up to 4.0 first (giving 6 here)
+            self.move_amount = f32::floor(rng.random(4.0) as f32) as i32 + 2;
         }
         if self.move_amount > 1 {
             if (mainchara_bbox.left as f32) > self.x {
@@ -155,6 +156,9 @@ struct SimulatedSnowball {
 pub const SNOWBALLS_ORIGIN_X: i32 = 150;
 pub const SNOWBALLS_ORIGIN_Y: i32 = 360;
 
+// Upper bound on RNG values used by a single snowball simulation (40 frames, 120 snowballs, up to 3 values each)
+pub const SNOWBALL_SIMULATION_MAX_RNG_VALUES: usize = 40 * 120 * 3;
+
 #[derive(Clone)]
 pub struct SnowballSimulator {
     pub snowballs: [Snowball; 120]
@@ -2,6 +2,8 @@ use sdl3::{sys::{properties::{SDL_CreateProperties, SDL_DestroyProperties, SDL_S
 use sdl3_sys::{properties::SDL_GetPointerProperty, video::{SDL_GetWindowProperties, SDL_PROP_WINDOW_WIN32_HWND_POINTER, SDL_SetWindowAlwaysOnTop, SDL_SetWindowFocusable}};
 use windows_sys::Win32::UI::WindowsAndMessaging::{GetWindowLongPtrA, SetWindowLongPtrA, GWL_EXSTYLE, WS_EX_APPWINDOW, WS_EX_TOPMOST};
 
+use crate::config::Config;
+
 pub fn focus_game_window() {
     if cfg!(windows) {
         unsafe { 
@@ -58,3 +60,22 @@ pub fn window_set_always_on_top(window: &mut Window, always_on_top: bool) -> boo
         false
     }
 }
+
+// Applies window settings from the config, for any that differ from the previous config (or the defaults, when starting up)
+pub fn apply_window_config(window: &mut Window, config: &Config, previous_config: &Config) {
+    if config.window_always_on_top != previous_config.window_always_on_top {
+        if !window_set_always_on_top(window, config.window_always_on_top) {
+            println!("Failed to change whether window is always on top");
+        }
+    }
+    if config.window_unfocusable_by_default != previous_config.window_unfocusable_by_default {
+        if !window_set_focusable(window, !config.window_unfocusable_by_default) {
+            println!("Failed to change whether window is focusable");
+        }
+    }
+    if config.window_opacity != previous_config.window_opacity {
+        if let Err(e) = window.set_opacity(config.window_opacity) {
+            println!("Failed to set window opacity: {}", e);
+        }
+    }
+}